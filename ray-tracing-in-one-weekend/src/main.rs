@@ -1,23 +1,179 @@
 use ray_tracing_utility::*;
-use rayon::prelude::*;
 use std::env;
+use std::error::Error;
 use std::fs;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // usage: ray-tracing-in-one-weekend [scene.json] [--save-scene out.json]
+    //        [--seed n] [--output image.{png,ppm,hdr,exr}]...
+    //        [--checkpoint render.ckpt [--checkpoint-interval seconds]]
+    //        [--spp n] [--time-limit seconds] [--noise-target error]
+    //        [--preview-interval seconds]
+    let mut scene_file = None;
+    let mut save_scene = None;
+    let mut seed = None;
+    let mut samples_per_pixel = None;
+    let mut time_limit = None;
+    let mut noise_target = None;
+    let mut preview_interval = None;
+    let mut checkpoint: Option<PathBuf> = None;
+    let mut checkpoint_interval = 60.;
+    let mut outputs: Vec<PathBuf> = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--save-scene" => save_scene = Some(args.next().ok_or("--save-scene needs a path")?),
+            "--seed" => seed = Some(args.next().ok_or("--seed needs a number")?.parse()?),
+            "--checkpoint" => {
+                checkpoint = Some(args.next().ok_or("--checkpoint needs a path")?.into())
+            }
+            "--checkpoint-interval" => {
+                checkpoint_interval = seconds("--checkpoint-interval", args.next())?
+            }
+            "--spp" => {
+                samples_per_pixel = Some(args.next().ok_or("--spp needs a number")?.parse()?)
+            }
+            "--time-limit" => {
+                time_limit = Some(
+                    args.next()
+                        .ok_or("--time-limit needs a number of seconds")?
+                        .parse()?,
+                )
+            }
+            "--noise-target" => {
+                noise_target = Some(
+                    args.next()
+                        .ok_or("--noise-target needs a number")?
+                        .parse()?,
+                )
+            }
+            "--preview-interval" => {
+                preview_interval = Some(Duration::from_secs_f64(seconds(
+                    "--preview-interval",
+                    args.next(),
+                )?))
+            }
+            "--output" => outputs.push(args.next().ok_or("--output needs a path")?.into()),
+            _ => scene_file = Some(arg),
+        }
+    }
+
+    // Scene: a scene file given on the command line, or the book's final scene
+    let (name, mut scene) = match scene_file {
+        Some(file) => {
+            let name = Path::new(&file)
+                .file_stem()
+                .map_or("scene".into(), |stem| stem.to_string_lossy().into_owned());
+            let mut scene = Scene::load(&file)?;
+            if let Some(seed) = seed {
+                scene.settings.seed = seed;
+            }
+            (name, scene)
+        }
+        None => (
+            "ray-tracing-in-one-weekend".into(),
+            default_scene(seed.unwrap_or_default()),
+        ),
+    };
+    let settings = &mut scene.settings;
+    settings.samples_per_pixel = samples_per_pixel.unwrap_or(settings.samples_per_pixel);
+    settings.time_limit = time_limit.or(settings.time_limit);
+    settings.noise_target = noise_target.or(settings.noise_target);
+    settings.validate()?;
+    if let Some(path) = save_scene {
+        scene.describe()?.save(&path)?;
+        eprintln!("Saved scene to {}", path);
+    }
+    let Scene {
+        world,
+        camera,
+        background,
+        settings,
+    } = scene;
 
-fn main() -> std::io::Result<()> {
     // Image
-    let path = env::current_dir()?.join("out");
-    let img_pmm = path.join("ray-tracing-in-one-weekend.ppm");
-    let img_png = path.join("ray-tracing-in-one-weekend.png");
-    let aspect_ratio = 3. / 2.;
-    let width = 1200;
-    let height = (width as f64 / aspect_ratio) as i32;
-    let samples_per_pixel = 500;
-    let max_depth = 100;
+    if outputs.is_empty() {
+        let path = env::current_dir()?.join("out");
+        fs::create_dir_all(&path)?;
+        outputs.push(path.join(format!("{}.ppm", name)));
+        outputs.push(path.join(format!("{}.png", name)));
+    }
 
     // World
-    let world = random_scene();
+    let world = BvhBuilder::default().build_list(&world);
+    eprintln!("{}", world.stats());
+
+    // Checkpoint: resume from it if there is one, and keep it up to date
+    let film = match &checkpoint {
+        Some(path) if path.exists() => {
+            eprintln!("Resuming from {}", path.display());
+            load_checkpoint(path, &settings)?
+        }
+        _ => Film::new(settings.width as usize, settings.height() as usize),
+    };
+    let checkpointer = checkpoint.map(|path| {
+        Checkpointer::new(path, settings, Duration::from_secs_f64(checkpoint_interval))
+    });
+
+    let reporter = ProgressReporter::default();
+    let mut renderer = Renderer::new(&world, &camera, settings)
+        .background(background)
+        .on_progress(|progress| reporter.report(progress));
+    if let Some(checkpointer) = &checkpointer {
+        renderer = renderer.on_tile(move |_, film| checkpointer.tile_done(film));
+    }
+    // Intermediate images, written over the final ones after a pass
+    if let Some(interval) = preview_interval {
+        let (outputs, last_preview) = (&outputs, Mutex::new(Instant::now()));
+        renderer = renderer.on_pass(move |film| {
+            let mut last = last_preview.lock().unwrap();
+            if last.elapsed() < interval {
+                return;
+            }
+            *last = Instant::now();
+            for output in outputs.iter() {
+                if let Err(error) = film.save(output, &settings.tone_mapping) {
+                    eprintln!("\rCould not save preview {}: {}", output.display(), error);
+                }
+            }
+        });
+    }
+    let film = renderer.render_from(film);
+    eprintln!("Noise {:.4}", film.noise());
+    for output in outputs.iter() {
+        film.save(output, &settings.tone_mapping)?;
+        eprintln!("Saved {}", output.display());
+    }
+    if let Some(checkpointer) = &checkpointer {
+        let path = checkpointer.path().display();
+        match checkpointer.finish(&film) {
+            Ok(()) => eprintln!("Saved checkpoint {}", path),
+            Err(error) => eprintln!("Could not save checkpoint {}: {}", path, error),
+        }
+    }
+    eprintln!("Done.");
+    Ok(())
+}
+
+/// A positive, finite number of seconds given for `flag`.
+fn seconds(flag: &str, arg: Option<String>) -> Result<f64, Box<dyn Error>> {
+    let seconds: f64 = arg
+        .ok_or_else(|| format!("{} needs a number of seconds", flag))?
+        .parse()?;
+    if !(seconds.is_finite() && seconds > 0.) {
+        return Err(format!("{} must be a positive number of seconds", flag).into());
+    }
+    Ok(seconds)
+}
+
+fn default_scene(seed: u64) -> Scene {
+    let settings = RenderSettings {
+        seed,
+        ..RenderSettings::default()
+    };
 
     // Camera
     let look_from = Point3::new(13., 2., 3.);
@@ -28,47 +184,21 @@ fn main() -> std::io::Result<()> {
         look_at,
         Vec3::new(0., 1., 0.),
         20.0,
-        aspect_ratio,
+        settings.aspect_ratio,
         0.1,
         10.,
     );
 
-    let all = (width * height) as f64 / 100.;
-    let count = Arc::new(Mutex::new(0.));
-    let mut img_str = format!("P3\n{} {}\n255\n", width, height);
-    let img_content = (0..height)
-        .rev()
-        .flat_map(|j| (0..width).map(|i| (i, j)).collect::<Vec<_>>())
-        .collect::<Vec<(i32, i32)>>()
-        .par_iter()
-        .map(|&(i, j)| {
-            let mut pixel_color = Color::new(0., 0., 0.);
-            for _ in 0..samples_per_pixel {
-                let u = i as f64 / width as f64;
-                let v = j as f64 / height as f64;
-                pixel_color += camera.get_ray(u, v).calc_color(&world, max_depth)
-            }
-            let mut c = count.lock().unwrap();
-            *c += 1.;
-            println!("{:.2}%", *c / all);
-            pixel_color.to_color_string(samples_per_pixel)
-        })
-        .collect::<Vec<String>>()
-        .join("\n");
-    img_str.push_str(&img_content);
-    img_str.push_str("\n");
-
-    fs::write(&img_pmm, &img_str).expect("Unable to write file");
-    Command::new("convert")
-        .arg(img_pmm)
-        .arg(img_png)
-        .status()
-        .expect("failed to execute process");
-    eprintln!("Done.");
-    Ok(())
+    Scene {
+        world: random_scene(seed),
+        camera,
+        background: Arc::new(GradientEnvironment::sky()),
+        settings,
+    }
 }
 
-fn random_scene() -> HittableList {
+fn random_scene(seed: u64) -> HittableList {
+    let mut rng = Pcg32::new(seed, 0);
     let mut world = HittableList::default();
 
     world.add(Arc::new(Sphere {
@@ -82,11 +212,11 @@ fn random_scene() -> HittableList {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = rand_f64();
+            let choose_mat = rng.next_f64();
             let center = Point3::new(
-                a as f64 + 0.9 * rand_f64(),
+                a as f64 + 0.9 * rng.next_f64(),
                 0.2,
-                b as f64 + 0.9 * rand_f64(),
+                b as f64 + 0.9 * rng.next_f64(),
             );
 
             if (center - Point3::new(4., 0.2, 0.)).length() <= 0.9 {
@@ -97,15 +227,15 @@ fn random_scene() -> HittableList {
                 _ if choose_mat < 0.8 => {
                     // diffuse
                     Lambertian {
-                        albedo: Color::random() * Color::random(),
+                        albedo: rng.next_vec3() * rng.next_vec3(),
                     }
                     .as_ref()
                 }
                 _ if choose_mat < 0.95 => {
                     // metal
                     Metal {
-                        albedo: Color::random_in_range(0.5, 1.),
-                        fuzz: rand_f64_in_range(0., 0.5),
+                        albedo: rng.next_vec3_in_range(0.5, 1.),
+                        fuzz: rng.next_f64_in_range(0., 0.5),
                     }
                     .as_ref()
                 }
@@ -0,0 +1,107 @@
+use crate::*;
+use std::sync::Arc;
+
+/// Bounding volume hierarchy node. Each node splits its objects in half
+/// along the longest axis of their centroids, so a ray only visits the
+/// subtrees whose boxes it actually crosses.
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: &HittableList) -> Self {
+        let mut objects = list.objects().to_vec();
+        Self::from_objects(&mut objects)
+    }
+
+    pub fn from_objects(objects: &mut [Arc<dyn Hittable>]) -> Self {
+        let boxes = objects.iter().map(|object| {
+            let mut bbox = Aabb::default();
+            if !object.bounding_box(&mut bbox) {
+                panic!("No bounding box in BvhNode constructor.");
+            }
+            bbox
+        });
+        let mut centroid_box: Option<Aabb> = None;
+        for bbox in boxes {
+            let c = bbox.centroid();
+            centroid_box = Some(match centroid_box {
+                Some(b) => surrounding_box(b, Aabb::new(c, c)),
+                None => Aabb::new(c, c),
+            });
+        }
+        let axis = centroid_box
+            .expect("BvhNode needs at least one object.")
+            .longest_axis();
+
+        let (left, right): (Arc<dyn Hittable>, Arc<dyn Hittable>) = match objects.len() {
+            1 => (objects[0].clone(), objects[0].clone()),
+            2 => (objects[0].clone(), objects[1].clone()),
+            len => {
+                objects.sort_by(|a, b| centroid(a, axis).total_cmp(&centroid(b, axis)));
+                let (l, r) = objects.split_at_mut(len / 2);
+                (
+                    Arc::new(BvhNode::from_objects(l)),
+                    Arc::new(BvhNode::from_objects(r)),
+                )
+            }
+        };
+
+        let mut box_left = Aabb::default();
+        let mut box_right = Aabb::default();
+        left.bounding_box(&mut box_left);
+        right.bounding_box(&mut box_right);
+
+        BvhNode {
+            left,
+            right,
+            bbox: surrounding_box(box_left, box_right),
+        }
+    }
+}
+
+fn centroid(object: &Arc<dyn Hittable>, axis: usize) -> f64 {
+    let mut bbox = Aabb::default();
+    object.bounding_box(&mut bbox);
+    bbox.centroid()[axis]
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(ray, t_min, t_max, record);
+        let hit_right = self
+            .right
+            .hit(ray, t_min, if hit_left { record.t } else { t_max }, record);
+
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.bbox.hit(ray, t_min, t_max)
+            && (self.left.occluded(ray, t_min, t_max) || self.right.occluded(ray, t_min, t_max))
+    }
+
+    fn describe(&self, exporter: &mut SceneExporter) -> bool {
+        // single-object nodes point both children at the same object
+        self.left.describe(exporter)
+            && (Arc::ptr_eq(&self.left, &self.right) || self.right.describe(exporter))
+    }
+
+    fn add_lights(&self, lights: &mut LightCollector) {
+        self.left.add_lights(lights);
+        if !Arc::ptr_eq(&self.left, &self.right) {
+            self.right.add_lights(lights);
+        }
+    }
+}
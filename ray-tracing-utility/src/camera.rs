@@ -1,11 +1,19 @@
 use crate::*;
 
 pub struct Camera {
+    // as passed to `new`, so the camera can be described again
+    look_from: Point3,
+    look_at: Point3,
+    vup: Vec3,
+    v_fov: f64,
+    aspect_ratio: f64,
+    aperture: f64,
+    focus_dist: f64,
+
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
     vertical: Vec3,
-    w: Vec3,
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
@@ -36,7 +44,13 @@ impl Camera {
         let lower_left_corner = origin - horizontal / 2. - vertical / 2. - focus_dist * w;
         let lens_radius = aperture / 2.;
         Camera {
-            w,
+            look_from,
+            look_at,
+            vup,
+            v_fov,
+            aspect_ratio,
+            aperture,
+            focus_dist,
             u,
             v,
             origin,
@@ -47,8 +61,36 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_sphere();
+    pub fn look_from(&self) -> Point3 {
+        self.look_from
+    }
+
+    pub fn look_at(&self) -> Point3 {
+        self.look_at
+    }
+
+    pub fn vup(&self) -> Vec3 {
+        self.vup
+    }
+
+    pub fn v_fov(&self) -> f64 {
+        self.v_fov
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    pub fn aperture(&self) -> f64 {
+        self.aperture
+    }
+
+    pub fn focus_dist(&self) -> f64 {
+        self.focus_dist
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * sample_unit_disk(sampler.get_2d());
         let offset = self.u * rd.x + self.v * rd.y;
 
         Ray {
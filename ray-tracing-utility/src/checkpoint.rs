@@ -0,0 +1,240 @@
+use crate::*;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 8] = b"RTCKPT\0\0";
+const VERSION: u32 = 3;
+
+/// Writes the per-pixel sample sums of `film` along with the
+/// settings that decide which samples a pixel gets. A sample's random
+/// numbers only depend on its pixel, its index and the seed, so the sample
+/// counts are all the sampler state a resumed render needs.
+pub fn write_checkpoint<W: Write>(
+    film: &Film,
+    settings: &RenderSettings,
+    mut writer: W,
+) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(film.width() as u32).to_le_bytes())?;
+    writer.write_all(&(film.height() as u32).to_le_bytes())?;
+    writer.write_all(&settings.seed.to_le_bytes())?;
+    writer.write_all(&settings.max_depth.to_le_bytes())?;
+    writer.write_all(&[sampler_id(settings.sampler)])?;
+    writer.write_all(&settings.samples_per_pixel.to_le_bytes())?;
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            let samples = film.samples(x, y);
+            let radiance = samples.radiance;
+            for value in [
+                radiance.x,
+                radiance.y,
+                radiance.z,
+                samples.luminance_squared,
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&samples.count.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a checkpoint written by [`write_checkpoint`]. Fails if it was made
+/// with a different image size, seed, bounce limit or sampler, as its
+/// samples wouldn't match the ones `settings` would trace. The number of
+/// samples per pixel may differ, except for samplers that lay their samples
+/// out for it (see [`SamplerKind::depends_on_sample_count`]).
+pub fn read_checkpoint<R: Read>(settings: &RenderSettings, mut reader: R) -> io::Result<Film> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a render checkpoint".into()));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported checkpoint version {}",
+            version
+        )));
+    }
+
+    let width = read_u32(&mut reader)?;
+    let height = read_u32(&mut reader)?;
+    let seed = read_u64(&mut reader)?;
+    let max_depth = read_u32(&mut reader)?;
+    let mut sampler = [0; 1];
+    reader.read_exact(&mut sampler)?;
+    let samples_per_pixel = read_u32(&mut reader)?;
+    let mismatch = [
+        ("width", width != settings.width),
+        ("height", height != settings.height()),
+        ("seed", seed != settings.seed),
+        ("max_depth", max_depth != settings.max_depth),
+        ("sampler", sampler[0] != sampler_id(settings.sampler)),
+        (
+            "samples_per_pixel",
+            samples_per_pixel != settings.samples_per_pixel
+                && settings.sampler.depends_on_sample_count(),
+        ),
+    ];
+    if let Some((key, _)) = mismatch.iter().find(|(_, differs)| *differs) {
+        return Err(invalid_data(format!(
+            "checkpoint was made with a different render.{}",
+            key
+        )));
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let mut film = Film::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let radiance = Color::new(
+                read_f64(&mut reader)?,
+                read_f64(&mut reader)?,
+                read_f64(&mut reader)?,
+            );
+            let samples = SampleSum {
+                radiance,
+                luminance_squared: read_f64(&mut reader)?,
+                count: read_u32(&mut reader)?,
+            };
+            film.add_samples(x, y, samples);
+        }
+    }
+    Ok(film)
+}
+
+/// Loads the checkpoint at `path`, see [`read_checkpoint`].
+pub fn load_checkpoint<P: AsRef<Path>>(path: P, settings: &RenderSettings) -> io::Result<Film> {
+    read_checkpoint(settings, BufReader::new(File::open(path)?))
+}
+
+/// Saves a checkpoint to `path` without ever leaving a half-written file
+/// there: it is written next to it first and then moved into place.
+pub fn save_checkpoint<P: AsRef<Path>>(
+    path: P,
+    film: &Film,
+    settings: &RenderSettings,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&temporary)?);
+    write_checkpoint(film, settings, &mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temporary, path)
+}
+
+/// Saves a checkpoint of the film at most once per interval. Meant as a
+/// [`Renderer::on_tile`](crate::Renderer::on_tile) hook: the hook only
+/// locks the film to copy it when a checkpoint is due, and a thread of its
+/// own writes the copy, so the render threads don't wait on the disk.
+/// Saves during the render stop at the first error; the render itself
+/// carries on.
+pub struct Checkpointer {
+    path: PathBuf,
+    settings: RenderSettings,
+    interval: Duration,
+    last_save: Mutex<Instant>,
+    snapshots: Mutex<Option<SyncSender<Film>>>,
+    // set while a copy waits for the writer
+    queued: Arc<AtomicBool>,
+    writer: Mutex<Option<JoinHandle<io::Result<()>>>>,
+}
+
+impl Checkpointer {
+    pub fn new<P: AsRef<Path>>(path: P, settings: RenderSettings, interval: Duration) -> Self {
+        let path = path.as_ref().to_path_buf();
+        // a snapshot only waits while the previous one is being written
+        let (sender, receiver) = mpsc::sync_channel::<Film>(1);
+        let queued = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let (path, queued) = (path.clone(), queued.clone());
+            thread::spawn(move || {
+                for film in receiver {
+                    queued.store(false, Ordering::Relaxed);
+                    save_checkpoint(&path, &film, &settings)?;
+                }
+                Ok(())
+            })
+        };
+        Checkpointer {
+            path,
+            settings,
+            interval,
+            last_save: Mutex::new(Instant::now()),
+            snapshots: Mutex::new(Some(sender)),
+            queued,
+            writer: Mutex::new(Some(writer)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hands a copy of `film` to the writer if the interval has gone by
+    /// since the last one. Skipped while the previous copy is still waiting
+    /// for the writer, or once the writer has failed.
+    pub fn tile_done(&self, film: &Mutex<Film>) {
+        let mut last_save = self.last_save.lock().unwrap();
+        if last_save.elapsed() < self.interval || self.queued.load(Ordering::Relaxed) {
+            return;
+        }
+        *last_save = Instant::now();
+        if let Some(snapshots) = &*self.snapshots.lock().unwrap() {
+            let snapshot = film.lock().unwrap().clone();
+            // stays set if the writer has gone, so no more copies are made
+            self.queued.store(true, Ordering::Relaxed);
+            let _ = snapshots.try_send(snapshot);
+        }
+    }
+
+    /// Waits for the writer and saves the finished film. The final save is
+    /// made even if one during the render failed, as it replaces whatever
+    /// that one left behind.
+    pub fn finish(&self, film: &Film) -> io::Result<()> {
+        drop(self.snapshots.lock().unwrap().take());
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            let _ = writer.join().expect("checkpoint writer panicked");
+        }
+        save_checkpoint(&self.path, film, &self.settings)
+    }
+}
+
+fn sampler_id(sampler: SamplerKind) -> u8 {
+    match sampler {
+        SamplerKind::Independent => 0,
+        SamplerKind::Stratified => 1,
+        SamplerKind::Halton => 2,
+        SamplerKind::Sobol => 3,
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
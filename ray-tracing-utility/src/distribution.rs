@@ -0,0 +1,217 @@
+/// A piecewise-constant function on [0, 1) that can be sampled in
+/// proportion to its value.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    // cdf[i] is the integral of func over [0, i / n), normalised
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// `func` holds the values of equally wide pieces; they must not be
+    /// negative. If they are all zero every piece is equally likely.
+    pub fn new(func: &[f64]) -> Self {
+        assert!(!func.is_empty(), "a distribution needs at least one value");
+        let n = func.len() as f64;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.);
+        for (i, value) in func.iter().enumerate() {
+            cdf.push(cdf[i] + value / n);
+        }
+        let integral = cdf[func.len()];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            *value = if integral > 0. {
+                *value / integral
+            } else {
+                i as f64 / n
+            };
+        }
+        Distribution1D {
+            func: func.to_vec(),
+            cdf,
+            integral,
+        }
+    }
+
+    /// Number of pieces.
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Integral of the function over [0, 1).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Turns a uniform `u` in [0, 1) into a point distributed in proportion
+    /// to the function. Returns the point, its density and the piece it
+    /// fell in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // the last piece whose cdf starts at or below u
+        let offset = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let mut du = u - self.cdf[offset];
+        if width > 0. {
+            du /= width;
+        }
+        let x = ((offset as f64 + du) / self.count() as f64).min(1. - f64::EPSILON / 2.);
+        (x, self.pdf_of(offset), offset)
+    }
+
+    /// Density of [`sample_continuous`](Distribution1D::sample_continuous)
+    /// returning `x`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        self.pdf_of(self.piece(x))
+    }
+
+    fn piece(&self, x: f64) -> usize {
+        ((x * self.count() as f64) as usize).min(self.count() - 1)
+    }
+
+    fn pdf_of(&self, piece: usize) -> f64 {
+        if self.integral > 0. {
+            self.func[piece] / self.integral
+        } else {
+            1.
+        }
+    }
+}
+
+/// A piecewise-constant function on the unit square, sampled by picking a
+/// row from the marginal distribution of the rows and then a column from
+/// that row.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `width * height` values, a row at a time; `u` runs along
+    /// the rows and `v` across them.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(func.len(), width * height, "wrong number of values");
+        let rows = func
+            .chunks_exact(width)
+            .map(Distribution1D::new)
+            .collect::<Vec<_>>();
+        let marginal =
+            Distribution1D::new(&rows.iter().map(|row| row.integral()).collect::<Vec<_>>());
+        Distribution2D { rows, marginal }
+    }
+
+    /// Integral of the function over the unit square.
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    /// Turns two uniform values into a point `[u, v]` distributed in
+    /// proportion to the function, and returns it with its density.
+    pub fn sample(&self, u: [f64; 2]) -> ([f64; 2], f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u[1]);
+        let (u, pdf_u, _) = self.rows[row].sample_continuous(u[0]);
+        ([u, v], pdf_u * pdf_v)
+    }
+
+    /// Density of [`sample`](Distribution2D::sample) returning `point`.
+    pub fn pdf(&self, point: [f64; 2]) -> f64 {
+        let row = self.marginal.piece(point[1]);
+        self.marginal.pdf_of(row) * self.rows[row].pdf(point[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FUNC: [f64; 6] = [0., 1., 0., 0., 2.5, 0.5];
+
+    // a 4 × 3 grid with a black row and a few black cells
+    const GRID: [f64; 12] = [0., 1., 2., 0., 0., 0., 0., 0., 3., 0.5, 0., 1.];
+
+    /// Evenly spread values in [0, 1), including the ends of every piece of
+    /// a `pieces`-piece function.
+    fn uniform(pieces: usize) -> impl Iterator<Item = f64> {
+        let n = pieces * 64;
+        (0..n).map(move |i| i as f64 / n as f64).chain([1. - 1e-12])
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-9 * b.abs().max(1.), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn density_integrates_to_one() {
+        let distribution = Distribution1D::new(&FUNC);
+        let n = 6000;
+        let integral: f64 = (0..n)
+            .map(|i| distribution.pdf((i as f64 + 0.5) / n as f64) / n as f64)
+            .sum();
+        assert_close(integral, 1.);
+        assert_close(distribution.integral(), 4. / 6.);
+
+        let distribution = Distribution2D::new(&GRID, 4, 3);
+        let n = 600;
+        let integral: f64 = (0..n * n)
+            .map(|i| {
+                let point = [(i % n) as f64 + 0.5, (i / n) as f64 + 0.5];
+                distribution.pdf([point[0] / n as f64, point[1] / n as f64]) / (n * n) as f64
+            })
+            .sum();
+        assert_close(integral, 1.);
+    }
+
+    #[test]
+    fn all_zero_function_is_uniform() {
+        let distribution = Distribution1D::new(&[0.; 4]);
+        for u in uniform(4) {
+            let (x, pdf, offset) = distribution.sample_continuous(u);
+            assert_close(x, u);
+            assert_eq!(pdf, 1.);
+            assert_eq!(offset, distribution.piece(x));
+        }
+    }
+
+    #[test]
+    fn sample_density_matches_pdf() {
+        let distribution = Distribution1D::new(&FUNC);
+        for u in uniform(FUNC.len()) {
+            let (x, pdf, offset) = distribution.sample_continuous(u);
+            assert!((0. ..1.).contains(&x));
+            assert_eq!(offset, distribution.piece(x));
+            assert_close(pdf, distribution.pdf(x));
+        }
+
+        let distribution = Distribution2D::new(&GRID, 4, 3);
+        for u in uniform(4) {
+            for v in uniform(3) {
+                let (point, pdf) = distribution.sample([u, v]);
+                assert_close(pdf, distribution.pdf(point));
+            }
+        }
+    }
+
+    #[test]
+    fn zero_pieces_are_never_sampled() {
+        let distribution = Distribution1D::new(&FUNC);
+        for u in uniform(FUNC.len()) {
+            let (x, pdf, offset) = distribution.sample_continuous(u);
+            assert!(FUNC[offset] > 0. && pdf > 0., "sampled piece {}", offset);
+            assert!(FUNC[distribution.piece(x)] > 0.);
+        }
+
+        let distribution = Distribution2D::new(&GRID, 4, 3);
+        for u in uniform(4) {
+            for v in uniform(3) {
+                let (point, pdf) = distribution.sample([u, v]);
+                let (x, y) = ((point[0] * 4.) as usize, (point[1] * 3.) as usize);
+                assert!(GRID[y * 4 + x] > 0. && pdf > 0., "sampled ({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use crate::*;
+use std::f64::consts::PI;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Light arriving from infinitely far away, seen by rays that leave the
+/// scene without hitting anything.
+pub trait Environment: Send + Sync {
+    /// Radiance arriving from `direction`, which needn't be a unit vector.
+    fn radiance(&self, direction: Vec3) -> Color;
+
+    /// Scene file description of this environment, if it has one.
+    fn describe(&self) -> Option<BackgroundDescription> {
+        None
+    }
+
+    /// Turns two uniform values into a unit direction to gather light from,
+    /// returned with its density per unit solid angle. Uniform over the
+    /// sphere unless the environment knows where its light comes from.
+    fn sample_direction(&self, u: [f64; 2]) -> (Vec3, f64) {
+        (sample_unit_sphere(u), UNIFORM_SPHERE_PDF)
+    }
+
+    /// Density of [`sample_direction`](Environment::sample_direction)
+    /// returning `direction`, which must be a unit vector.
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        UNIFORM_SPHERE_PDF
+    }
+
+    /// True if no light at all comes from the environment, so it needn't
+    /// be sampled as a light.
+    fn is_black(&self) -> bool {
+        false
+    }
+}
+
+const UNIFORM_SPHERE_PDF: f64 = 1. / (4. * PI);
+
+/// Orientation and brightness of an environment.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EnvironmentTransform {
+    /// Rotation of the environment about the x, y and z axes, in degrees,
+    /// applied in that order.
+    pub rotation: [f64; 3],
+    /// Multiplies the radiance.
+    pub intensity: f64,
+    // environment to world, and its inverse
+    to_world: Matrix3,
+    to_local: Matrix3,
+}
+
+impl Default for EnvironmentTransform {
+    fn default() -> Self {
+        EnvironmentTransform::new([0., 0., 0.], 1.)
+    }
+}
+
+impl EnvironmentTransform {
+    pub fn new(rotation: [f64; 3], intensity: f64) -> Self {
+        let [x, y, z] = rotation.map(f64::to_radians);
+        let rx = [
+            [1., 0., 0.],
+            [0., x.cos(), -x.sin()],
+            [0., x.sin(), x.cos()],
+        ];
+        let ry = [
+            [y.cos(), 0., y.sin()],
+            [0., 1., 0.],
+            [-y.sin(), 0., y.cos()],
+        ];
+        let rz = [
+            [z.cos(), -z.sin(), 0.],
+            [z.sin(), z.cos(), 0.],
+            [0., 0., 1.],
+        ];
+        let to_world = mul_matrix(&rz, &mul_matrix(&ry, &rx));
+        // a rotation's inverse is its transpose
+        let mut to_local = [[0.; 3]; 3];
+        for (i, row) in to_local.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = to_world[j][i];
+            }
+        }
+        EnvironmentTransform {
+            rotation,
+            intensity,
+            to_world,
+            to_local,
+        }
+    }
+
+    /// A world space direction in the environment's own frame.
+    pub fn to_local(&self, direction: Vec3) -> Vec3 {
+        mul_vector(&self.to_local, direction)
+    }
+
+    /// A direction in the environment's own frame in world space.
+    pub fn to_world(&self, direction: Vec3) -> Vec3 {
+        mul_vector(&self.to_world, direction)
+    }
+}
+
+/// The same radiance in every direction.
+#[derive(Copy, Clone)]
+pub struct SolidEnvironment {
+    pub color: Color,
+}
+
+impl Environment for SolidEnvironment {
+    fn radiance(&self, _direction: Vec3) -> Color {
+        self.color
+    }
+
+    fn is_black(&self) -> bool {
+        <[f64; 3]>::from(self.color) == [0.; 3]
+    }
+
+    fn describe(&self) -> Option<BackgroundDescription> {
+        Some(if <[f64; 3]>::from(self.color) == [0.; 3] {
+            BackgroundDescription::Black
+        } else {
+            BackgroundDescription::Solid(self.color.into())
+        })
+    }
+}
+
+/// Blends from `bottom` straight down to `top` straight up.
+#[derive(Copy, Clone)]
+pub struct GradientEnvironment {
+    pub bottom: Color,
+    pub top: Color,
+    pub transform: EnvironmentTransform,
+}
+
+impl GradientEnvironment {
+    /// The book's sky, white below and light blue above.
+    pub fn sky() -> Self {
+        GradientEnvironment {
+            bottom: Color::new(1., 1., 1.),
+            top: Color::new(0.5, 0.7, 1.),
+            transform: EnvironmentTransform::default(),
+        }
+    }
+}
+
+impl Environment for GradientEnvironment {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let unit = self.transform.to_local(direction).unit();
+        let t = 0.5 * (unit.y + 1.);
+        (self.bottom * (1. - t) + self.top * t) * self.transform.intensity
+    }
+
+    fn describe(&self) -> Option<BackgroundDescription> {
+        let sky = GradientEnvironment::sky();
+        let array = |c: Color| <[f64; 3]>::from(c);
+        let is_sky = array(self.bottom) == array(sky.bottom)
+            && array(self.top) == array(sky.top)
+            && self.transform == sky.transform;
+        Some(if is_sky {
+            BackgroundDescription::Sky
+        } else {
+            BackgroundDescription::Gradient {
+                bottom: self.bottom.into(),
+                top: self.top.into(),
+                rotation: self.transform.rotation,
+                intensity: self.transform.intensity,
+            }
+        })
+    }
+}
+
+/// A latitude-longitude image of the whole sphere of directions. The
+/// centre of the image is straight ahead along -z, its left and right
+/// edges are behind along +z, and its top row is straight up.
+///
+/// Directions are sampled in proportion to the luminance of the image, so
+/// a small bright sun in it gets most of the samples it deserves.
+#[derive(Clone)]
+pub struct EquirectEnvironment {
+    image: HdrImage,
+    pub transform: EnvironmentTransform,
+    // over the image's pixels, weighted by how much of the sphere they cover
+    distribution: Distribution2D,
+    // where the image came from, for the scene description
+    path: Option<PathBuf>,
+}
+
+impl EquirectEnvironment {
+    pub fn new(image: HdrImage, transform: EnvironmentTransform) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            // rows near the poles are squeezed into a smaller solid angle
+            let sin_theta = ((y as f64 + 0.5) / height as f64 * PI).sin();
+            func.extend((0..width).map(|x| luminance(image.get(x, y)).max(0.) * sin_theta));
+        }
+        EquirectEnvironment {
+            distribution: Distribution2D::new(&func, width, height),
+            image,
+            transform,
+            path: None,
+        }
+    }
+
+    /// Loads the image from a Radiance `.hdr` file.
+    pub fn load<P: AsRef<Path>>(path: P, transform: EnvironmentTransform) -> io::Result<Self> {
+        let path = path.as_ref();
+        Ok(EquirectEnvironment {
+            path: Some(path.to_path_buf()),
+            ..EquirectEnvironment::new(load_hdr(path)?, transform)
+        })
+    }
+
+    pub fn image(&self) -> &HdrImage {
+        &self.image
+    }
+
+    /// Image coordinates, `v` running down, of a direction in the
+    /// environment's own frame.
+    pub fn direction_to_uv(direction: Vec3) -> (f64, f64) {
+        let unit = direction.unit();
+        let u = 0.5 + unit.x.atan2(-unit.z) / (2. * PI);
+        let v = unit.y.clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    /// Inverse of [`direction_to_uv`](EquirectEnvironment::direction_to_uv),
+    /// giving a unit vector.
+    pub fn uv_to_direction(u: f64, v: f64) -> Vec3 {
+        let phi = (u - 0.5) * 2. * PI;
+        let theta = v * PI;
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl Environment for EquirectEnvironment {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = EquirectEnvironment::direction_to_uv(self.transform.to_local(direction));
+        self.image.sample(u, v, true) * self.transform.intensity
+    }
+
+    fn sample_direction(&self, u: [f64; 2]) -> (Vec3, f64) {
+        let ([u, v], pdf) = self.distribution.sample(u);
+        let sin_theta = (v * PI).sin();
+        if pdf == 0. || sin_theta == 0. {
+            return (Vec3::new(0., 1., 0.), 0.);
+        }
+        let direction = EquirectEnvironment::uv_to_direction(u, v);
+        // the image spans 2π by π radians, and a pixel's solid angle shrinks
+        // with sin θ
+        (
+            self.transform.to_world(direction),
+            pdf / (2. * PI * PI * sin_theta),
+        )
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = EquirectEnvironment::direction_to_uv(self.transform.to_local(direction));
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0. {
+            return 0.;
+        }
+        self.distribution.pdf([u, v]) / (2. * PI * PI * sin_theta)
+    }
+
+    fn describe(&self) -> Option<BackgroundDescription> {
+        Some(BackgroundDescription::Equirect {
+            path: self.path.clone()?,
+            rotation: self.transform.rotation,
+            intensity: self.transform.intensity,
+        })
+    }
+}
+
+/// Six square images on the faces of a cube around the scene, in the
+/// order +x, -x, +y, -y, +z, -z, laid out as OpenGL cube maps are: seen
+/// from inside the cube, with the side faces' tops towards +y.
+#[derive(Clone)]
+pub struct CubeMapEnvironment {
+    pub faces: Vec<HdrImage>,
+    pub transform: EnvironmentTransform,
+    paths: Option<[PathBuf; 6]>,
+}
+
+impl CubeMapEnvironment {
+    pub fn new(faces: [HdrImage; 6], transform: EnvironmentTransform) -> Self {
+        CubeMapEnvironment {
+            faces: Vec::from(faces),
+            transform,
+            paths: None,
+        }
+    }
+
+    /// Loads the faces from Radiance `.hdr` files.
+    pub fn load<P: AsRef<Path>>(
+        paths: [P; 6],
+        transform: EnvironmentTransform,
+    ) -> io::Result<Self> {
+        let paths = paths.map(|path| path.as_ref().to_path_buf());
+        let faces = paths.iter().map(load_hdr).collect::<io::Result<Vec<_>>>()?;
+        Ok(CubeMapEnvironment {
+            faces,
+            transform,
+            paths: Some(paths),
+        })
+    }
+}
+
+impl Environment for CubeMapEnvironment {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let d = self.transform.to_local(direction);
+        let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+        // the face of the major axis, and the direction's position on it
+        let (face, s, t, major) = if ax >= ay && ax >= az {
+            if d.x > 0. {
+                (0, -d.z, -d.y, ax)
+            } else {
+                (1, d.z, -d.y, ax)
+            }
+        } else if ay >= az {
+            if d.y > 0. {
+                (2, d.x, d.z, ay)
+            } else {
+                (3, d.x, -d.z, ay)
+            }
+        } else if d.z > 0. {
+            (4, d.x, -d.y, az)
+        } else {
+            (5, -d.x, -d.y, az)
+        };
+        if major == 0. {
+            return Color::default();
+        }
+        let (u, v) = (0.5 * (s / major + 1.), 0.5 * (t / major + 1.));
+        self.faces[face].sample(u, v, false) * self.transform.intensity
+    }
+
+    fn describe(&self) -> Option<BackgroundDescription> {
+        Some(BackgroundDescription::CubeMap {
+            faces: self.paths.clone()?,
+            rotation: self.transform.rotation,
+            intensity: self.transform.intensity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8 × 4 image, black but for a few pixels, one of them bright.
+    fn environment() -> EquirectEnvironment {
+        let mut image = HdrImage::new(8, 4);
+        image.set(1, 0, Color::new(0.5, 0.5, 0.5));
+        image.set(2, 1, Color::new(40., 20., 10.));
+        image.set(6, 2, Color::new(0., 1., 0.));
+        image.set(7, 3, Color::new(1., 1., 1.));
+        EquirectEnvironment::new(image, EnvironmentTransform::new([10., 30., -20.], 2.))
+    }
+
+    fn grid(n: usize) -> impl Iterator<Item = [f64; 2]> {
+        (0..n * n).map(move |i| {
+            [
+                ((i % n) as f64 + 0.5) / n as f64,
+                ((i / n) as f64 + 0.5) / n as f64,
+            ]
+        })
+    }
+
+    #[test]
+    fn equirect_sample_density_matches_pdf() {
+        let environment = environment();
+        for u in grid(61) {
+            let (direction, pdf) = environment.sample_direction(u);
+            let expected = environment.pdf(direction);
+            assert!(pdf > 0.);
+            assert!(
+                (pdf - expected).abs() <= 1e-9 * expected,
+                "{} != {}",
+                pdf,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn equirect_never_samples_black_pixels() {
+        let environment = environment();
+        for u in grid(61) {
+            let (direction, _) = environment.sample_direction(u);
+            let local = environment.transform.to_local(direction);
+            let (u, v) = EquirectEnvironment::direction_to_uv(local);
+            let (x, y) = ((u * 8.) as usize, (v * 4.) as usize);
+            assert!(
+                luminance(environment.image.get(x, y)) > 0.,
+                "sampled ({}, {})",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn equirect_density_integrates_to_one() {
+        let environment = environment();
+        // midpoint rule over θ and φ, with dω = sin θ dθ dφ
+        let (n_theta, n_phi) = (400, 800);
+        let mut integral = 0.;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) / n_theta as f64 * PI;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) / n_phi as f64 * 2. * PI;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                integral += environment.pdf(direction) * theta.sin();
+            }
+        }
+        integral *= PI / n_theta as f64 * 2. * PI / n_phi as f64;
+        assert!((integral - 1.).abs() < 1e-2, "integral {}", integral);
+    }
+}
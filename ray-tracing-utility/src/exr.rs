@@ -0,0 +1,162 @@
+use crate::*;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExrCompression {
+    None,
+    /// zlib over blocks of 16 scanlines.
+    Zip,
+}
+
+impl ExrCompression {
+    fn id(self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(self) -> usize {
+        match self {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => 16,
+        }
+    }
+}
+
+/// Writes a single-part scanline OpenEXR image with 32-bit float R, G and B
+/// channels.
+pub fn write_exr<W: Write>(
+    film: &Film,
+    mut writer: W,
+    compression: ExrCompression,
+) -> io::Result<()> {
+    let (width, height) = (film.width(), film.height());
+    if width == 0 || height == 0 || width > i32::MAX as usize || height > i32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't store a {}x{} image as exr", width, height),
+        ));
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&20000630u32.to_le_bytes());
+    // version 2, single-part scanline file
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    let mut channels = Vec::new();
+    // channels are listed, and stored, in alphabetical order
+    for name in [b"B", b"G", b"R"].iter() {
+        channels.extend_from_slice(*name);
+        channels.push(0);
+        // FLOAT pixels, not perceptually linear, 3 reserved bytes, no subsampling
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(
+        &mut header,
+        "compression",
+        "compression",
+        &[compression.id()],
+    );
+    let window = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|n: &i32| n.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    // increasing y
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let lines = compression.lines_per_block();
+    let blocks = (0..height)
+        .step_by(lines)
+        .map(|y0| {
+            let mut raw = Vec::with_capacity(lines * width * 12);
+            for y in y0..(y0 + lines).min(height) {
+                for c in (0..3).rev() {
+                    for x in 0..width {
+                        raw.extend_from_slice(&(film.pixel(x, y)[c] as f32).to_le_bytes());
+                    }
+                }
+            }
+            let data = match compression {
+                ExrCompression::None => raw,
+                ExrCompression::Zip => zip_block(raw)?,
+            };
+            let mut block = Vec::with_capacity(data.len() + 8);
+            block.extend_from_slice(&(y0 as i32).to_le_bytes());
+            block.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            block.extend_from_slice(&data);
+            Ok(block)
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    // the offset table points at each block from the start of the file
+    writer.write_all(&header)?;
+    let mut offset = (header.len() + 8 * blocks.len()) as u64;
+    for block in blocks.iter() {
+        writer.write_all(&offset.to_le_bytes())?;
+        offset += block.len() as u64;
+    }
+    for block in blocks.iter() {
+        writer.write_all(block)?;
+    }
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// EXR's ZIP scheme splits even and odd bytes into two halves and
+/// delta-encodes the result before deflating it. Blocks that don't shrink
+/// are stored as they are, which readers detect from the size.
+fn zip_block(raw: Vec<u8>) -> io::Result<Vec<u8>> {
+    let half = raw.len().div_ceil(2);
+    let mut shuffled = vec![0u8; raw.len()];
+    for (i, &byte) in raw.iter().enumerate() {
+        let target = if i % 2 == 0 { i / 2 } else { half + i / 2 };
+        shuffled[target] = byte;
+    }
+    let mut previous = shuffled[0];
+    for byte in shuffled.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&shuffled)?;
+    let compressed = encoder.finish()?;
+    Ok(if compressed.len() < raw.len() {
+        compressed
+    } else {
+        raw
+    })
+}
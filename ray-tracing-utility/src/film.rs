@@ -0,0 +1,148 @@
+use crate::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::AddAssign;
+use std::path::Path;
+
+/// Running sums over some of the samples of a pixel.
+#[derive(Copy, Clone, Default)]
+pub struct SampleSum {
+    pub radiance: Color,
+    /// Sum of the squared luminance of the samples, for their variance.
+    pub luminance_squared: f64,
+    pub count: u32,
+}
+
+impl SampleSum {
+    pub fn add(&mut self, radiance: Color) {
+        self.radiance += radiance;
+        self.luminance_squared += luminance(radiance).powi(2);
+        self.count += 1;
+    }
+}
+
+impl AddAssign for SampleSum {
+    fn add_assign(&mut self, other: Self) {
+        self.radiance += other.radiance;
+        self.luminance_squared += other.luminance_squared;
+        self.count += other.count;
+    }
+}
+
+/// Relative luminance of linear sRGB.
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// Accumulates linear radiance samples per pixel, top row first. Nothing is
+/// clamped or tone mapped until the film is turned into an 8-bit image, so
+/// the HDR writers get the radiance exactly as it was traced.
+#[derive(Clone)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    pixels: Vec<SampleSum>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![SampleSum::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn add_sample(&mut self, x: usize, y: usize, radiance: Color) {
+        self.pixels[y * self.width + x].add(radiance);
+    }
+
+    pub fn add_samples(&mut self, x: usize, y: usize, samples: SampleSum) {
+        self.pixels[y * self.width + x] += samples;
+    }
+
+    /// Everything added to a pixel so far.
+    pub fn samples(&self, x: usize, y: usize) -> SampleSum {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn sample_count(&self, x: usize, y: usize) -> u32 {
+        self.samples(x, y).count
+    }
+
+    /// Mean radiance of a pixel, black if it has no samples yet.
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        let samples = self.samples(x, y);
+        match samples.count {
+            0 => Color::default(),
+            n => samples.radiance / n as f64,
+        }
+    }
+
+    /// Standard error of a pixel's mean luminance relative to the mean,
+    /// which halves every time the sample count quadruples. Pixels darker
+    /// than `1 / 64` are measured against that instead, so the noise in the
+    /// near-black parts of the image doesn't dominate. Infinite until the
+    /// pixel has two samples.
+    pub fn relative_error(&self, x: usize, y: usize) -> f64 {
+        let samples = self.samples(x, y);
+        if samples.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = samples.count as f64;
+        let mean = luminance(samples.radiance) / n;
+        let variance = (samples.luminance_squared / n - mean * mean).max(0.) * n / (n - 1.);
+        (variance / n).sqrt() / mean.max(1. / 64.)
+    }
+
+    /// Mean [`relative_error`](Film::relative_error) of all pixels.
+    pub fn noise(&self) -> f64 {
+        let pixels = (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y)));
+        let total: f64 = pixels.map(|(x, y)| self.relative_error(x, y)).sum();
+        total / (self.width * self.height) as f64
+    }
+
+    /// Mean radiance of every pixel, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = Color> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| self.pixel(x, y)))
+    }
+
+    /// Tone maps the film to an 8-bit sRGB image.
+    pub fn to_image(&self, tone_mapping: &ToneMapping) -> RgbImage {
+        let pixels = self
+            .pixels()
+            .map(|c| tone_mapping.to_rgb8(c))
+            .collect::<Vec<_>>();
+        RgbImage::from_pixels(self.width, self.height, TransferFunction::Srgb, &pixels)
+    }
+
+    /// Writes the film in the format picked by the file extension. `.hdr`
+    /// and `.exr` (ZIP compressed) keep the linear radiance, `.png` and
+    /// `.ppm` are tone mapped with `tone_mapping`.
+    pub fn save<P: AsRef<Path>>(&self, path: P, tone_mapping: &ToneMapping) -> io::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("hdr") | Some("exr") => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                if extension.as_deref() == Some("hdr") {
+                    write_hdr(self, &mut writer)?;
+                } else {
+                    write_exr(self, &mut writer, ExrCompression::Zip)?;
+                }
+                writer.flush()
+            }
+            _ => self.to_image(tone_mapping).save(path),
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use crate::*;
+use std::fmt;
+use std::sync::Arc;
+
+// Past this depth nodes are split at the median, which keeps the tree
+// shallow enough for the fixed-size traversal stack.
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+
+/// Builds a [`FlatBvh`] by binning primitive centroids and picking the split
+/// with the lowest surface area heuristic cost.
+#[derive(Copy, Clone)]
+pub struct BvhBuilder {
+    // set through the builder methods, which keep them in the range the
+    // build and the node layout can handle
+    max_leaf_size: usize,
+    bin_count: usize,
+    traversal_cost: f64,
+    intersection_cost: f64,
+}
+
+impl Default for BvhBuilder {
+    fn default() -> Self {
+        BvhBuilder {
+            max_leaf_size: 4,
+            bin_count: 12,
+            traversal_cost: 1.,
+            intersection_cost: 1.,
+        }
+    }
+}
+
+/// Node of the depth-first flattened tree. The left child of an interior
+/// node always sits right after it, so only the right child is stored.
+#[derive(Copy, Clone)]
+struct LinearBvhNode {
+    bbox: Aabb,
+    // first primitive for leaves, right child for interior nodes
+    offset: u32,
+    count: u16,
+    axis: u8,
+}
+
+pub struct FlatBvh {
+    nodes: Vec<LinearBvhNode>,
+    indices: Vec<u32>,
+    stats: BvhStats,
+}
+
+#[derive(Clone, Default)]
+pub struct BvhStats {
+    pub primitive_count: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    /// `leaf_size_histogram[n]` is the number of leaves holding `n` primitives.
+    pub leaf_size_histogram: Vec<usize>,
+    /// Expected cost of a ray through the tree, relative to the root area.
+    pub sah_cost: f64,
+}
+
+#[derive(Copy, Clone)]
+struct BuildPrimitive {
+    bbox: Aabb,
+    centroid: Point3,
+    index: u32,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Bin {
+    count: usize,
+    bbox: Option<Aabb>,
+}
+
+fn union(a: Option<Aabb>, b: Aabb) -> Option<Aabb> {
+    Some(match a {
+        Some(a) => surrounding_box(a, b),
+        None => b,
+    })
+}
+
+fn surface_area(bbox: &Aabb) -> f64 {
+    let d = bbox.extent();
+    2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+impl BvhBuilder {
+    pub fn max_leaf_size(mut self, max_leaf_size: usize) -> Self {
+        self.max_leaf_size = max_leaf_size.clamp(1, u16::MAX as usize);
+        self
+    }
+
+    pub fn bin_count(mut self, bin_count: usize) -> Self {
+        self.bin_count = bin_count.max(2);
+        self
+    }
+
+    pub fn costs(mut self, traversal_cost: f64, intersection_cost: f64) -> Self {
+        self.traversal_cost = traversal_cost;
+        self.intersection_cost = intersection_cost;
+        self
+    }
+
+    /// Builds a tree over primitives given only by their bounds. The leaves
+    /// refer back to positions in `bounds`.
+    pub fn build(&self, bounds: &[Aabb]) -> FlatBvh {
+        let mut primitives = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, &bbox)| BuildPrimitive {
+                bbox,
+                centroid: bbox.centroid(),
+                index: index as u32,
+            })
+            .collect::<Vec<_>>();
+
+        let mut bvh = FlatBvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: Vec::with_capacity(bounds.len()),
+            stats: BvhStats {
+                primitive_count: bounds.len(),
+                ..BvhStats::default()
+            },
+        };
+        if !primitives.is_empty() {
+            self.build_recursive(&mut bvh, &mut primitives, 1);
+            let root_area = surface_area(&bvh.nodes[0].bbox);
+            if root_area > 0. {
+                bvh.stats.sah_cost /= root_area;
+            }
+        }
+        bvh.stats.node_count = bvh.nodes.len();
+        bvh
+    }
+
+    /// Builds a tree over the objects of `list`. Objects without bounds,
+    /// such as meshes with no faces, have nothing to hit and are left out.
+    pub fn build_list(&self, list: &HittableList) -> BvhAccel {
+        let (objects, bounds): (Vec<_>, Vec<_>) = list
+            .objects()
+            .iter()
+            .filter_map(|object| {
+                let mut bbox = Aabb::default();
+                object
+                    .bounding_box(&mut bbox)
+                    .then(|| (object.clone(), bbox))
+            })
+            .unzip();
+        BvhAccel {
+            bvh: self.build(&bounds),
+            objects,
+        }
+    }
+
+    fn build_recursive(
+        &self,
+        bvh: &mut FlatBvh,
+        primitives: &mut [BuildPrimitive],
+        depth: usize,
+    ) -> usize {
+        let mut bbox = primitives[0].bbox;
+        let mut centroid_box = Aabb::new(primitives[0].centroid, primitives[0].centroid);
+        for p in primitives.iter() {
+            bbox = surrounding_box(bbox, p.bbox);
+            centroid_box = surrounding_box(centroid_box, Aabb::new(p.centroid, p.centroid));
+        }
+
+        let node_index = bvh.nodes.len();
+        bvh.nodes.push(LinearBvhNode {
+            bbox,
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+        bvh.stats.max_depth = bvh.stats.max_depth.max(depth);
+
+        let count = primitives.len();
+        let axis = centroid_box.longest_axis();
+        let lo = centroid_box.minimum[axis];
+        let hi = centroid_box.maximum[axis];
+
+        let split = if count == 1 {
+            None
+        } else if depth >= MAX_SAH_DEPTH || hi <= lo {
+            // coincident centroids can't be binned
+            if count > self.max_leaf_size {
+                primitives.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+                Some(count / 2)
+            } else {
+                None
+            }
+        } else {
+            self.find_split(primitives, &bbox, axis, lo, hi)
+        };
+
+        match split {
+            Some(mid) => {
+                bvh.stats.sah_cost += self.traversal_cost * surface_area(&bbox);
+                self.build_recursive(bvh, &mut primitives[..mid], depth + 1);
+                let right = self.build_recursive(bvh, &mut primitives[mid..], depth + 1);
+                let node = &mut bvh.nodes[node_index];
+                node.offset = right as u32;
+                node.axis = axis as u8;
+            }
+            None => self.make_leaf(bvh, node_index, primitives),
+        }
+        node_index
+    }
+
+    /// Returns where to partition `primitives`, which gets reordered so the
+    /// left child is everything before that point, or `None` for a leaf.
+    fn find_split(
+        &self,
+        primitives: &mut [BuildPrimitive],
+        bbox: &Aabb,
+        axis: usize,
+        lo: f64,
+        hi: f64,
+    ) -> Option<usize> {
+        let count = primitives.len();
+        let bin_of = |p: &BuildPrimitive| {
+            let b = (self.bin_count as f64 * (p.centroid[axis] - lo) / (hi - lo)) as usize;
+            b.min(self.bin_count - 1)
+        };
+
+        let mut bins = vec![Bin::default(); self.bin_count];
+        for p in primitives.iter() {
+            let bin = &mut bins[bin_of(p)];
+            bin.count += 1;
+            bin.bbox = union(bin.bbox, p.bbox);
+        }
+
+        // sweep from the right first so each split is evaluated in one pass
+        let mut right_area = vec![0.; self.bin_count];
+        let mut right_count = vec![0; self.bin_count];
+        let mut acc_box: Option<Aabb> = None;
+        let mut acc_count = 0;
+        for i in (1..self.bin_count).rev() {
+            if let Some(b) = bins[i].bbox {
+                acc_box = union(acc_box, b);
+            }
+            acc_count += bins[i].count;
+            right_area[i] = acc_box.as_ref().map_or(0., surface_area);
+            right_count[i] = acc_count;
+        }
+
+        let mut best_cost = f64::INFINITY;
+        let mut best_bin = 0;
+        let mut acc_box: Option<Aabb> = None;
+        let mut acc_count = 0;
+        for i in 0..self.bin_count - 1 {
+            if let Some(b) = bins[i].bbox {
+                acc_box = union(acc_box, b);
+            }
+            acc_count += bins[i].count;
+            if acc_count == 0 || right_count[i + 1] == 0 {
+                continue;
+            }
+            let left_area = acc_box.as_ref().map_or(0., surface_area);
+            let cost = left_area * acc_count as f64 + right_area[i + 1] * right_count[i + 1] as f64;
+            if cost < best_cost {
+                best_cost = cost;
+                best_bin = i;
+            }
+        }
+
+        let area = surface_area(bbox);
+        let split_cost = self.traversal_cost + self.intersection_cost * best_cost / area;
+        let leaf_cost = self.intersection_cost * count as f64;
+        if best_cost.is_finite() && (count > self.max_leaf_size || split_cost < leaf_cost) {
+            let mut mid = 0;
+            for i in 0..count {
+                if bin_of(&primitives[i]) <= best_bin {
+                    primitives.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            Some(mid)
+        } else if count > self.max_leaf_size {
+            // every centroid fell in one bin, fall back to a median split
+            primitives.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            Some(count / 2)
+        } else {
+            None
+        }
+    }
+
+    fn make_leaf(&self, bvh: &mut FlatBvh, node_index: usize, primitives: &[BuildPrimitive]) {
+        let count = primitives.len();
+        let node = &mut bvh.nodes[node_index];
+        node.offset = bvh.indices.len() as u32;
+        node.count = count as u16;
+        bvh.indices.extend(primitives.iter().map(|p| p.index));
+
+        let stats = &mut bvh.stats;
+        stats.leaf_count += 1;
+        if stats.leaf_size_histogram.len() <= count {
+            stats.leaf_size_histogram.resize(count + 1, 0);
+        }
+        stats.leaf_size_histogram[count] += 1;
+        stats.sah_cost += self.intersection_cost * count as f64 * surface_area(&node.bbox);
+    }
+}
+
+fn hit_box(bbox: &Aabb, origin: Point3, inv_d: Vec3, t_min: f64, t_max: f64) -> bool {
+    let mut t_min = t_min;
+    let mut t_max = t_max;
+    for axis in 0..3 {
+        let mut t0 = (bbox.minimum[axis] - origin[axis]) * inv_d[axis];
+        let mut t1 = (bbox.maximum[axis] - origin[axis]) * inv_d[axis];
+        if inv_d[axis].is_sign_negative() {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        t_min = t_min.max(t0);
+        t_max = t_max.min(t1);
+        if t_max < t_min {
+            return false;
+        }
+    }
+    true
+}
+
+impl FlatBvh {
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bbox)
+    }
+
+    /// Walks the tree front to back. `hit_primitive` is called with a
+    /// primitive index and the closest hit distance found so far, and
+    /// returns the distance of a closer hit, if there is one.
+    pub fn traverse<F>(&self, ray: &Ray, t_min: f64, t_max: f64, mut hit_primitive: F) -> bool
+    where
+        F: FnMut(usize, f64) -> Option<f64>,
+    {
+        let mut hit_anything = false;
+        self.walk(ray, t_min, t_max, |index, closest_so_far| {
+            if let Some(t) = hit_primitive(index, *closest_so_far) {
+                hit_anything = true;
+                *closest_so_far = t;
+            }
+            false
+        });
+        hit_anything
+    }
+
+    /// Like [`traverse`](FlatBvh::traverse), but stops at the first
+    /// primitive `hit_primitive` says is hit, in no particular order. For
+    /// shadow rays, which only need to know whether anything is in the way.
+    pub fn any_hit<F>(&self, ray: &Ray, t_min: f64, t_max: f64, mut hit_primitive: F) -> bool
+    where
+        F: FnMut(usize, f64) -> bool,
+    {
+        let mut hit_anything = false;
+        self.walk(ray, t_min, t_max, |index, t_max| {
+            hit_anything = hit_primitive(index, *t_max);
+            hit_anything
+        });
+        hit_anything
+    }
+
+    /// Calls `visit` with every primitive in a leaf the ray reaches before
+    /// `t_max`, which `visit` may lower; it returns true to stop the walk.
+    fn walk<F>(&self, ray: &Ray, t_min: f64, t_max: f64, mut visit: F)
+    where
+        F: FnMut(usize, &mut f64) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inv_d = Vec3::new(
+            1. / ray.direction.x,
+            1. / ray.direction.y,
+            1. / ray.direction.z,
+        );
+        let dir_is_neg = [
+            inv_d.x.is_sign_negative(),
+            inv_d.y.is_sign_negative(),
+            inv_d.z.is_sign_negative(),
+        ];
+
+        let mut t_max = t_max;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if hit_box(&node.bbox, ray.origin, inv_d, t_min, t_max) {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    for &index in &self.indices[first..first + node.count as usize] {
+                        if visit(index as usize, &mut t_max) {
+                            return;
+                        }
+                    }
+                } else {
+                    // visit the child nearer to the ray origin first
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "bvh: {} primitives, {} nodes, {} leaves, max depth {}, sah cost {:.2}",
+            self.primitive_count, self.node_count, self.leaf_count, self.max_depth, self.sah_cost
+        )?;
+        write!(f, "leaf sizes:")?;
+        for (size, &n) in self.leaf_size_histogram.iter().enumerate() {
+            if n > 0 {
+                write!(f, " {}x{}", size, n)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A [`HittableList`] behind a SAH-built [`FlatBvh`].
+pub struct BvhAccel {
+    objects: Vec<Arc<dyn Hittable>>,
+    bvh: FlatBvh,
+}
+
+impl BvhAccel {
+    pub fn new(list: &HittableList) -> Self {
+        BvhBuilder::default().build_list(list)
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        self.bvh.stats()
+    }
+}
+
+impl Hittable for BvhAccel {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool {
+        self.bvh
+            .traverse(ray, t_min, t_max, |index, closest_so_far| {
+                if self.objects[index].hit(ray, t_min, closest_so_far, record) {
+                    Some(record.t)
+                } else {
+                    None
+                }
+            })
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bvh.bounding_box() {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.bvh.any_hit(ray, t_min, t_max, |index, t_max| {
+            self.objects[index].occluded(ray, t_min, t_max)
+        })
+    }
+
+    fn describe(&self, exporter: &mut SceneExporter) -> bool {
+        self.objects.iter().all(|object| object.describe(exporter))
+    }
+
+    fn add_lights(&self, lights: &mut LightCollector) {
+        for object in self.objects.iter() {
+            object.add_lights(lights);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// Linear RGB image read from a Radiance file, top row first.
+#[derive(Clone)]
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        HdrImage {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Bilinearly filtered value at `(u, v)` in `[0, 1]`, with `v` running
+    /// down the image. `u` wraps around if `wrap_u` is set and is clamped to
+    /// the edges otherwise; `v` is always clamped.
+    pub fn sample(&self, u: f64, v: f64, wrap_u: bool) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = (v * self.height as f64 - 0.5).clamp(0., (self.height - 1) as f64);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let column = |x: f64| {
+            let (x, w) = (x as isize, self.width as isize);
+            if wrap_u {
+                x.rem_euclid(w) as usize
+            } else {
+                x.clamp(0, w - 1) as usize
+            }
+        };
+        let (xa, xb) = (column(x0), column(x0 + 1.));
+        let (ya, yb) = (y0 as usize, (y0 as usize + 1).min(self.height - 1));
+        let top = self.get(xa, ya) * (1. - fx) + self.get(xb, ya) * fx;
+        let bottom = self.get(xa, yb) * (1. - fx) + self.get(xb, yb) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+}
+
+/// Loads a Radiance RGBE (`.hdr`) image.
+pub fn load_hdr<P: AsRef<Path>>(path: P) -> io::Result<HdrImage> {
+    read_hdr(BufReader::new(File::open(path)?))
+}
+
+/// Reads a Radiance RGBE (`.hdr`) image with the usual `-Y h +X w`
+/// orientation, flat or run-length encoded.
+pub fn read_hdr<R: BufRead>(mut reader: R) -> io::Result<HdrImage> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("not a Radiance HDR file".into()));
+    }
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("header has no end".into()));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(format!("unsupported format {}", format)));
+            }
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let size = line.split_whitespace().collect::<Vec<_>>();
+    let (width, height) = match size.as_slice() {
+        ["-Y", height, "+X", width] => (width.parse().ok(), height.parse().ok()),
+        _ => (None, None),
+    };
+    let (width, height): (usize, usize) = match (width, height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported resolution line '{}'",
+                line.trim_end()
+            )))
+        }
+    };
+
+    let mut image = HdrImage::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for y in 0..height {
+        read_scanline(&mut reader, &mut scanline)?;
+        for (x, rgbe) in scanline.iter().enumerate() {
+            image.set(x, y, from_rgbe(*rgbe));
+        }
+    }
+    Ok(image)
+}
+
+fn read_scanline<R: Read>(reader: &mut R, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut first = [0; 4];
+    reader.read_exact(&mut first)?;
+    let encoded = first[0] == 2 && first[1] == 2 && first[2] & 0x80 == 0;
+    if !(8..0x8000).contains(&width) || !encoded {
+        // flat pixels, possibly with old-style runs of the previous pixel
+        let mut x = 0;
+        let mut shift = 0;
+        let mut rgbe = first;
+        loop {
+            if rgbe[..3] == [1, 1, 1] && x > 0 {
+                let count = (rgbe[3] as usize) << shift;
+                if x + count > width {
+                    return Err(invalid_data("run past the end of a scanline".into()));
+                }
+                let previous = scanline[x - 1];
+                scanline[x..x + count].fill(previous);
+                x += count;
+                shift += 8;
+            } else {
+                scanline[x] = rgbe;
+                x += 1;
+                shift = 0;
+            }
+            if x == width {
+                return Ok(());
+            }
+            reader.read_exact(&mut rgbe)?;
+        }
+    }
+
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err(invalid_data(
+            "scanline width doesn't match the image".into(),
+        ));
+    }
+    for c in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0; 1];
+            reader.read_exact(&mut count)?;
+            let (count, run) = match count[0] {
+                n if n > 128 => (n as usize - 128, true),
+                n => (n as usize, false),
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("bad run length in a scanline".into()));
+            }
+            if run {
+                let mut value = [0; 1];
+                reader.read_exact(&mut value)?;
+                for rgbe in &mut scanline[x..x + count] {
+                    rgbe[c] = value[0];
+                }
+            } else {
+                let mut values = [0; 128];
+                reader.read_exact(&mut values[..count])?;
+                for (rgbe, value) in scanline[x..x + count].iter_mut().zip(&values) {
+                    rgbe[c] = *value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::default();
+    }
+    // each mantissa stands for the middle of the range it was rounded from
+    let scale = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+    let channel = |m: u8| (m as f64 + 0.5) * scale;
+    Color::new(channel(rgbe[0]), channel(rgbe[1]), channel(rgbe[2]))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Writes a Radiance RGBE (`.hdr`) image. Scanlines are run-length encoded
+/// when the width allows it, as most readers expect.
+pub fn write_hdr<W: Write>(film: &Film, mut writer: W) -> io::Result<()> {
+    let (width, height) = (film.width(), film.height());
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut channel = vec![0u8; width];
+    for y in 0..height {
+        for (x, rgbe) in scanline.iter_mut().enumerate() {
+            *rgbe = to_rgbe(film.pixel(x, y));
+        }
+        // the RLE scheme only covers widths that fit its 15-bit length
+        if !(8..0x8000).contains(&width) {
+            for rgbe in scanline.iter() {
+                writer.write_all(rgbe)?;
+            }
+            continue;
+        }
+        writer.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+        for c in 0..4 {
+            for (value, rgbe) in channel.iter_mut().zip(scanline.iter()) {
+                *value = rgbe[c];
+            }
+            write_rle(&mut writer, &channel)?;
+        }
+    }
+    Ok(())
+}
+
+/// Shared-exponent encoding: the mantissas of all three channels are scaled
+/// by the exponent of the largest one.
+fn to_rgbe(color: Color) -> [u8; 4] {
+    // negative and NaN radiance can't be stored
+    let channel = |n: f64| if n > 0. { n } else { 0. };
+    let (r, g, b) = (channel(color.x), channel(color.y), channel(color.z));
+    let max = r.max(g).max(b);
+    if max < 1e-32 || !max.is_finite() {
+        return [0; 4];
+    }
+    let mut exponent = max.log2().floor() as i32 + 1;
+    // log2 can round up just below a power of two
+    if max >= 2f64.powi(exponent) {
+        exponent += 1;
+    }
+    if exponent > 127 {
+        return [255, 255, 255, 255];
+    }
+    let scale = 256. / 2f64.powi(exponent);
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+/// A run is a count above 128 followed by one byte to repeat, anything else
+/// is a count of literal bytes that follow.
+fn write_rle<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    const MIN_RUN: usize = 4;
+    let mut start = 0;
+    while start < data.len() {
+        // find the next run long enough to be worth encoding
+        let mut run_start = start;
+        let mut run_length = 0;
+        while run_start < data.len() {
+            run_length = 1;
+            while run_length < 127
+                && run_start + run_length < data.len()
+                && data[run_start + run_length] == data[run_start]
+            {
+                run_length += 1;
+            }
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+        if run_start >= data.len() {
+            run_length = 0;
+        }
+
+        for literals in data[start..run_start].chunks(128) {
+            writer.write_all(&[literals.len() as u8])?;
+            writer.write_all(literals)?;
+        }
+        if run_length > 0 {
+            writer.write_all(&[128 + run_length as u8, data[run_start]])?;
+        }
+        start = run_start + run_length;
+    }
+    Ok(())
+}
@@ -4,7 +4,15 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct HitRecord {
     pub point: Point3,
+    /// Shading normal, facing against the incoming ray.
     pub normal: Vec3,
+    /// True surface normal, on the same side as `normal`.
+    pub geometric_normal: Vec3,
+    /// Barycentric weights of the hit point on a triangle.
+    pub barycentric: Vec3,
+    /// Surface (texture) coordinates of the hit point.
+    pub u: f64,
+    pub v: f64,
     pub t: f64,
     pub front_face: bool,
     pub material: Arc<dyn Material>,
@@ -15,6 +23,10 @@ impl Default for HitRecord {
         HitRecord {
             point: Vec3::from((0, 0, 0)),
             normal: Vec3::from((0, 0, 0)),
+            geometric_normal: Vec3::from((0, 0, 0)),
+            barycentric: Vec3::from((0, 0, 0)),
+            u: 0f64,
+            v: 0f64,
             t: 0f64,
             front_face: true,
             material: Arc::new(Lambertian::new(0, 0, 0)),
@@ -22,8 +34,27 @@ impl Default for HitRecord {
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool;
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool;
+
+    /// Whether the ray hits anything between `t_min` and `t_max`. Unlike
+    /// [`hit`](Hittable::hit) it needn't find the closest hit, so it can
+    /// stop at the first one.
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit(ray, t_min, t_max, &mut HitRecord::default())
+    }
+
+    /// Adds scene file descriptions of this object to `exporter`. Returns
+    /// false if it can't be described.
+    fn describe(&self, _exporter: &mut SceneExporter) -> bool {
+        false
+    }
+
+    /// Adds the surfaces of this object with emissive materials to
+    /// `lights`. Objects that add none can't be sampled as lights, though
+    /// rays that hit them still see their light.
+    fn add_lights(&self, _lights: &mut LightCollector) {}
 }
 
 impl HitRecord {
@@ -34,6 +65,22 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+
+    /// Replaces the shading normal with an interpolated vertex normal,
+    /// flipped onto the side of the geometric normal so that vertex normals
+    /// that disagree with the face winding can't point into the surface.
+    pub fn set_shading_normal(&mut self, shading_normal: Vec3) {
+        if shading_normal.length_squared() == 0. {
+            return;
+        }
+        let n = shading_normal.unit();
+        self.normal = if n.dot(self.geometric_normal).is_sign_negative() {
+            -n
+        } else {
+            n
+        };
     }
 }
 
@@ -73,17 +120,60 @@ impl Hittable for Sphere {
             }
         }
         record.t = root;
-        record.point = ray.at(record.t).clone();
-        record.set_face_normal(ray, (record.point - self.center) / self.radius);
+        record.point = ray.at(record.t);
+        let outward_normal = (record.point - self.center) / self.radius;
+        record.set_face_normal(ray, outward_normal);
+        let (u, v) = get_sphere_uv(outward_normal);
+        record.u = u;
+        record.v = v;
         record.material = self.material.clone();
         true
     }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let r = self.radius.abs();
+        let r = Vec3::new(r, r, r);
+        *output_box = Aabb::new(self.center - r, self.center + r);
+        true
+    }
+
+    fn describe(&self, exporter: &mut SceneExporter) -> bool {
+        match exporter.material(&self.material) {
+            Some(material) => {
+                exporter.add(ObjectDescription::Sphere {
+                    center: self.center.into(),
+                    radius: self.radius,
+                    material,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn add_lights(&self, lights: &mut LightCollector) {
+        lights.add(
+            &self.material,
+            LightShape::Sphere {
+                center: self.center,
+                radius: self.radius.abs(),
+            },
+        );
+    }
 }
 
-unsafe impl Send for HittableList {}
-unsafe impl Sync for HittableList {}
+/// Maps a point on the unit sphere to `u` around the Y axis from X=-1 and
+/// `v` from Y=-1 to Y=+1, both in [0, 1].
+pub fn get_sphere_uv(p: Point3) -> (f64, f64) {
+    let theta = (-p.y).acos();
+    let phi = (-p.z).atan2(p.x) + std::f64::consts::PI;
+    (
+        phi / (2. * std::f64::consts::PI),
+        theta / std::f64::consts::PI,
+    )
+}
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct HittableList {
     objects: Vec<Arc<dyn Hittable>>,
 }
@@ -95,13 +185,9 @@ impl HittableList {
     pub fn clear(&mut self) {
         self.objects.clear();
     }
-}
 
-impl Default for HittableList {
-    fn default() -> Self {
-        HittableList {
-            objects: Vec::new(),
-        }
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        &self.objects
     }
 }
 
@@ -121,8 +207,47 @@ impl Hittable for HittableList {
 
         hit_anything
     }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        if self.objects.is_empty() {
+            return false;
+        }
+
+        let mut temp_box = Aabb::default();
+        let mut first_box = true;
+
+        for object in self.objects.iter() {
+            if !object.bounding_box(&mut temp_box) {
+                return false;
+            }
+            *output_box = if first_box {
+                temp_box
+            } else {
+                surrounding_box(*output_box, temp_box)
+            };
+            first_box = false;
+        }
+
+        true
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.occluded(ray, t_min, t_max))
+    }
+
+    fn describe(&self, exporter: &mut SceneExporter) -> bool {
+        self.objects.iter().all(|object| object.describe(exporter))
+    }
+
+    fn add_lights(&self, lights: &mut LightCollector) {
+        for object in self.objects.iter() {
+            object.add_lights(lights);
+        }
+    }
 }
 
 pub fn degrees_to_radians(degrees: f64) -> f64 {
-    return degrees * std::f64::consts::PI / 180.;
+    degrees * std::f64::consts::PI / 180.
 }
@@ -0,0 +1,212 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// How the 8-bit values of an image relate to linear light. This is what
+/// the colour chunks of a PNG advertise to viewers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TransferFunction {
+    Srgb,
+    /// A plain power curve, encoded as `linear.powf(1. / gamma)`.
+    Gamma(f64),
+}
+
+/// An 8-bit RGB image, stored top row first.
+#[derive(Clone)]
+pub struct RgbImage {
+    width: usize,
+    height: usize,
+    transfer: TransferFunction,
+    pixels: Vec<u8>,
+}
+
+impl RgbImage {
+    pub fn new(width: usize, height: usize, transfer: TransferFunction) -> Self {
+        RgbImage {
+            width,
+            height,
+            transfer,
+            pixels: vec![0; width * height * 3],
+        }
+    }
+
+    pub fn from_pixels(
+        width: usize,
+        height: usize,
+        transfer: TransferFunction,
+        pixels: &[[u8; 3]],
+    ) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "image needs one pixel per slot"
+        );
+        RgbImage {
+            width,
+            height,
+            transfer,
+            pixels: pixels.iter().flatten().copied().collect(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn transfer(&self) -> TransferFunction {
+        self.transfer
+    }
+
+    /// Raw RGB bytes, row by row.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * self.width + x) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let i = (y * self.width + x) * 3;
+        self.pixels[i..i + 3].copy_from_slice(&rgb);
+    }
+
+    /// Writes the image in the format picked by the file extension,
+    /// `.png` or `.ppm`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let write: fn(&Self, &mut BufWriter<File>) -> io::Result<()> = match extension.as_deref() {
+            Some("png") => |image, w| image.write_png(w),
+            Some("ppm") => |image, w| image.write_ppm(w),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: unknown image format", path.display()),
+                ))
+            }
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        write(self, &mut writer)?;
+        writer.flush()
+    }
+
+    /// Binary (P6) PPM.
+    pub fn write_ppm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.pixels)
+    }
+
+    pub fn write_png<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        // dimensions are stored as non-zero 31-bit integers
+        let limit = 1 << 31;
+        if self.width == 0 || self.height == 0 || self.width >= limit || self.height >= limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("can't store a {}x{} image as png", self.width, self.height),
+            ));
+        }
+
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per channel, truecolour, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(&mut writer, b"IHDR", &header)?;
+
+        // gAMA holds 1 / gamma scaled by 100000. The spec asks for sRGB files to
+        // carry a matching gAMA too, for decoders that don't know sRGB.
+        let gamma = match self.transfer {
+            TransferFunction::Srgb => {
+                // rendering intent: perceptual
+                write_chunk(&mut writer, b"sRGB", &[0])?;
+                45455
+            }
+            TransferFunction::Gamma(gamma) => (100000. / gamma).round() as u32,
+        };
+        write_chunk(&mut writer, b"gAMA", &gamma.to_be_bytes())?;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let stride = self.width * 3;
+        let mut previous = vec![0; stride];
+        let mut filtered = vec![0; stride + 1];
+        for row in self.pixels.chunks_exact(stride) {
+            filter_row(row, &previous, &mut filtered);
+            encoder.write_all(&filtered)?;
+            previous.copy_from_slice(row);
+        }
+        write_chunk(&mut writer, b"IDAT", &encoder.finish()?)?;
+
+        write_chunk(&mut writer, b"IEND", &[])
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.finalize().to_be_bytes())
+}
+
+/// Tries every PNG filter on a scanline and keeps the one with the smallest
+/// sum of absolute (signed) residuals, the heuristic the spec recommends.
+/// `out` receives the filter type byte followed by the filtered row.
+fn filter_row(row: &[u8], previous: &[u8], out: &mut [u8]) {
+    const BPP: usize = 3;
+    let mut best = u64::MAX;
+    let mut candidate = vec![0; row.len()];
+    for filter in 0..5u8 {
+        for i in 0..row.len() {
+            let a = if i >= BPP { row[i - BPP] } else { 0 };
+            let b = previous[i];
+            let c = if i >= BPP { previous[i - BPP] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                _ => paeth(a, b, c),
+            };
+            candidate[i] = row[i].wrapping_sub(predicted);
+        }
+        let cost = candidate
+            .iter()
+            .map(|&x| (x as i8).unsigned_abs() as u64)
+            .sum();
+        if cost < best {
+            best = cost;
+            out[0] = filter;
+            out[1..].copy_from_slice(&candidate);
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
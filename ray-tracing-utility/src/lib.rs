@@ -1,13 +1,61 @@
+pub mod aabb;
+pub mod bvh;
 pub mod camera;
+pub mod checkpoint;
+pub mod distribution;
+pub mod environment;
+pub mod exr;
+pub mod film;
+pub mod flat_bvh;
+pub mod hdr;
 pub mod hittable;
+pub mod image;
+pub mod light;
 pub mod materials;
+pub mod mesh;
+pub mod obj;
+pub mod ply;
+pub mod progress;
 pub mod ray;
+pub mod render;
+pub mod rng;
+pub mod sampler;
+pub mod scene;
+pub mod sky;
+pub mod stl;
+pub mod tile;
+pub mod tonemap;
+pub mod triangle;
 pub mod vec3;
 
+pub use aabb::*;
+pub use bvh::*;
 pub use camera::*;
+pub use checkpoint::*;
+pub use distribution::*;
+pub use environment::*;
+pub use exr::*;
+pub use film::*;
+pub use flat_bvh::*;
+pub use hdr::*;
 pub use hittable::*;
+pub use image::*;
+pub use light::*;
 pub use materials::*;
+pub use mesh::*;
+pub use obj::*;
+pub use ply::*;
+pub use progress::*;
 pub use ray::*;
+pub use render::*;
+pub use rng::*;
+pub use sampler::*;
+pub use scene::*;
+pub use sky::*;
+pub use stl::*;
+pub use tile::*;
+pub use tonemap::*;
+pub use triangle::*;
 pub use vec3::*;
 
 use rand::Rng;
@@ -0,0 +1,400 @@
+use crate::*;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Light arriving at a point from a direction picked by [`Light::sample`].
+#[derive(Copy, Clone)]
+pub struct LightSample {
+    /// Unit vector from the lit point towards the light.
+    pub direction: Vec3,
+    /// How far along `direction` the light is; infinite for lights that
+    /// are infinitely far away.
+    pub distance: f64,
+    pub radiance: Color,
+    /// Density of picking `direction`, per unit solid angle.
+    pub pdf: f64,
+}
+
+/// Something that emits light and can pick directions towards itself, for
+/// lighting a point directly rather than waiting for a scattered ray to
+/// happen to hit it.
+pub trait Light: Send + Sync {
+    /// Picks a direction from `point` towards the light, in proportion to
+    /// the light arriving from it where the light can manage that. `None`
+    /// if no light arrives from the direction picked.
+    fn sample(&self, point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample>;
+
+    /// Density of [`sample`](Light::sample) picking `direction` from
+    /// `point`, per unit solid angle.
+    fn pdf(&self, point: Point3, direction: Vec3) -> f64;
+}
+
+/// An [`Environment`] as a light, sampled the way the environment knows
+/// best.
+#[derive(Clone)]
+pub struct EnvironmentLight {
+    pub environment: Arc<dyn Environment>,
+}
+
+impl EnvironmentLight {
+    pub fn new(environment: Arc<dyn Environment>) -> Self {
+        EnvironmentLight { environment }
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample(&self, _point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let (direction, pdf) = self.environment.sample_direction(sampler.get_2d());
+        if pdf <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.environment.radiance(direction),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, _point: Point3, direction: Vec3) -> f64 {
+        self.environment.pdf(direction)
+    }
+}
+
+/// A surface light can be sampled on, by area.
+#[derive(Copy, Clone)]
+pub enum LightShape {
+    Sphere { center: Point3, radius: f64 },
+    Triangle([Point3; 3]),
+}
+
+impl LightShape {
+    pub fn area(&self) -> f64 {
+        match *self {
+            LightShape::Sphere { radius, .. } => 4. * PI * radius * radius,
+            LightShape::Triangle([p0, p1, p2]) => 0.5 * (p1 - p0).cross(p2 - p0).length(),
+        }
+    }
+
+    /// Uniformly distributed point on the surface, with the surface normal
+    /// there and its surface coordinates as a hit would give them.
+    pub fn sample(&self, u: [f64; 2]) -> (Point3, Vec3, f64, f64) {
+        match *self {
+            LightShape::Sphere { center, radius } => {
+                let normal = sample_unit_sphere(u);
+                let (u, v) = get_sphere_uv(normal);
+                (center + normal * radius, normal, u, v)
+            }
+            LightShape::Triangle([p0, p1, p2]) => {
+                let s = u[0].sqrt();
+                let (b1, b2) = (s * (1. - u[1]), s * u[1]);
+                let point = (1. - b1 - b2) * p0 + b1 * p1 + b2 * p2;
+                (point, (p1 - p0).cross(p2 - p0).unit(), b1, b2)
+            }
+        }
+    }
+
+    /// Distance along `ray` to the nearest hit between `t_min` and `t_max`,
+    /// with the surface normal there.
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, Vec3)> {
+        match *self {
+            LightShape::Sphere { center, radius } => {
+                let sphere = Sphere::new(center, radius);
+                let mut record = HitRecord::default();
+                if sphere.hit(ray, t_min, t_max, &mut record) {
+                    Some((record.t, record.normal))
+                } else {
+                    None
+                }
+            }
+            LightShape::Triangle([p0, p1, p2]) => intersect_triangle(ray, p0, p1, p2, t_min, t_max)
+                .map(|(t, _)| (t, (p1 - p0).cross(p2 - p0).unit())),
+        }
+    }
+}
+
+/// Every surface with one emissive material, sampled uniformly by area.
+pub struct AreaLight {
+    material: Arc<dyn Material>,
+    shapes: Vec<LightShape>,
+    // picks a shape in proportion to its area
+    distribution: Distribution1D,
+    area: f64,
+}
+
+impl AreaLight {
+    pub fn new(material: Arc<dyn Material>, shapes: Vec<LightShape>) -> Self {
+        let areas = shapes.iter().map(LightShape::area).collect::<Vec<_>>();
+        AreaLight {
+            material,
+            shapes,
+            distribution: Distribution1D::new(&areas),
+            area: areas.iter().sum(),
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    /// Density per unit solid angle of sampling `point`, a point on the
+    /// light with surface normal `normal`, from `from`.
+    fn pdf_at(&self, from: Point3, point: Point3, normal: Vec3) -> f64 {
+        let to_light = point - from;
+        let cosine = normal.dot(to_light.unit()).abs();
+        if cosine == 0. || self.area == 0. {
+            return 0.;
+        }
+        to_light.length_squared() / (cosine * self.area)
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let (_, _, shape) = self.distribution.sample_continuous(sampler.get_1d());
+        let (light_point, normal, u, v) = self.shapes[shape].sample(sampler.get_2d());
+        let to_light = light_point - point;
+        let distance = to_light.length();
+        let pdf = self.pdf_at(point, light_point, normal);
+        if distance == 0. || pdf == 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.material.emitted(u, v, &light_point),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, point: Point3, direction: Vec3) -> f64 {
+        let ray = Ray::new(point, direction);
+        let nearest = self
+            .shapes
+            .iter()
+            .filter_map(|shape| shape.intersect(&ray, 0., f64::INFINITY))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        match nearest {
+            Some((t, normal)) => self.pdf_at(point, ray.at(t), normal),
+            None => 0.,
+        }
+    }
+}
+
+/// Gathers a world's emissive surfaces, grouped by material, for a
+/// [`LightList`]. See [`Hittable::add_lights`].
+#[derive(Default)]
+pub struct LightCollector {
+    lights: Vec<(Arc<dyn Material>, Vec<LightShape>)>,
+    indices: HashMap<usize, usize>,
+}
+
+impl LightCollector {
+    /// Adds `shape` to the light of `material`; does nothing unless the
+    /// material is emissive.
+    pub fn add(&mut self, material: &Arc<dyn Material>, shape: LightShape) {
+        if !material.is_emissive() {
+            return;
+        }
+        let lights = &mut self.lights;
+        let index = *self
+            .indices
+            .entry(material_key(material))
+            .or_insert_with(|| {
+                lights.push((material.clone(), Vec::new()));
+                lights.len() - 1
+            });
+        self.lights[index].1.push(shape);
+    }
+}
+
+fn material_key(material: &Arc<dyn Material>) -> usize {
+    Arc::as_ptr(material) as *const () as usize
+}
+
+/// The lights of a scene: an [`AreaLight`] for each emissive material in
+/// the world and the environment, unless it is black. Direct lighting
+/// picks one of them uniformly at random.
+pub struct LightList {
+    lights: Vec<Arc<dyn Light>>,
+    // total area of each emissive material's surfaces
+    areas: HashMap<usize, f64>,
+    environment: Option<Arc<dyn Light>>,
+}
+
+impl LightList {
+    pub fn new(world: &dyn Hittable, environment: Arc<dyn Environment>) -> Self {
+        let mut collector = LightCollector::default();
+        world.add_lights(&mut collector);
+        let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+        let mut areas = HashMap::new();
+        for (material, shapes) in collector.lights {
+            let light = AreaLight::new(material.clone(), shapes);
+            areas.insert(material_key(&material), light.area());
+            lights.push(Arc::new(light));
+        }
+        let environment = if environment.is_black() {
+            None
+        } else {
+            let light: Arc<dyn Light> = Arc::new(EnvironmentLight::new(environment));
+            lights.push(light.clone());
+            Some(light)
+        };
+        LightList {
+            lights,
+            areas,
+            environment,
+        }
+    }
+
+    pub fn lights(&self) -> &[Arc<dyn Light>] {
+        &self.lights
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Picks a light and samples it from `point`. The density includes the
+    /// chance of picking that light.
+    pub fn sample(&self, point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let count = self.lights.len();
+        let index = ((sampler.get_1d() * count as f64) as usize).min(count - 1);
+        let mut sample = self.lights[index].sample(point, sampler)?;
+        sample.pdf /= count as f64;
+        Some(sample)
+    }
+
+    /// Density of [`sample`](LightList::sample) picking the direction of
+    /// `ray` from its origin, given that the ray hit an emissive surface as
+    /// described by `record`.
+    pub fn surface_pdf(&self, ray: &Ray, record: &HitRecord) -> f64 {
+        match self.areas.get(&material_key(&record.material)) {
+            Some(&area) if area > 0. => {
+                let distance = record.t * ray.direction.length();
+                let cosine = record.geometric_normal.dot(ray.direction.unit()).abs();
+                if cosine == 0. {
+                    return 0.;
+                }
+                distance * distance / (cosine * area) / self.lights.len() as f64
+            }
+            _ => 0.,
+        }
+    }
+
+    /// Density of [`sample`](LightList::sample) picking `direction`, a unit
+    /// vector, from `point`, given that nothing is in the way of the
+    /// environment.
+    pub fn environment_pdf(&self, point: Point3, direction: Vec3) -> f64 {
+        match &self.environment {
+            Some(light) => light.pdf(point, direction) / self.lights.len() as f64,
+            None => 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light_material() -> Arc<dyn Material> {
+        DiffuseLight::new(Color::new(4., 4., 4.)).as_ref()
+    }
+
+    fn triangle() -> [Point3; 3] {
+        [
+            Point3::new(5., -1., -1.),
+            Point3::new(5., 1., -1.),
+            Point3::new(6., 0., 2.),
+        ]
+    }
+
+    // the cosine at the light is poorly conditioned at grazing angles
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-5 * b.abs(), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn area_light_sample_density_matches_pdf() {
+        let shapes = vec![
+            LightShape::Triangle(triangle()),
+            LightShape::Sphere {
+                center: Point3::new(0., 5., 0.),
+                radius: 1.,
+            },
+        ];
+        let light = AreaLight::new(light_material(), shapes);
+        let point = Point3::new(0., 0., 0.);
+        let mut sampler = IndependentSampler::new(3);
+        let mut checked = 0;
+        for _ in 0..2000 {
+            let sample = light.sample(point, &mut sampler).unwrap();
+            let light_point = point + sample.direction * sample.distance;
+            // points on the far side of the sphere are hidden by its near side
+            let (center, radius) = (Point3::new(0., 5., 0.), 1.);
+            let on_sphere = ((light_point - center).length() - radius).abs() < 1e-9;
+            if on_sphere && (light_point - center).dot(sample.direction) > 0. {
+                continue;
+            }
+            assert_close(sample.pdf, light.pdf(point, sample.direction));
+            checked += 1;
+        }
+        assert!(checked > 500);
+    }
+
+    #[test]
+    fn light_list_densities_match_sampling() {
+        let mut world = HittableList::default();
+        let [v0, v1, v2] = triangle();
+        world.add(Arc::new(Triangle::new(v0, v1, v2, light_material())));
+        world.add(Arc::new(Sphere {
+            center: Point3::new(0., 5., 0.),
+            radius: 1.,
+            material: light_material(),
+        }));
+        world.add(Arc::new(Sphere {
+            center: Point3::new(0., -5., 0.),
+            radius: 2.,
+            material: Lambertian::new(128, 128, 128).as_ref(),
+        }));
+        let environment = Arc::new(SolidEnvironment {
+            color: Color::new(0.5, 0.5, 0.5),
+        });
+        let lights = LightList::new(&world, environment);
+        assert_eq!(lights.lights().len(), 3);
+
+        let point = Point3::new(0., 0., 0.);
+        let mut sampler = IndependentSampler::new(5);
+        let (mut surfaces, mut misses) = (0, 0);
+        for _ in 0..3000 {
+            let sample = lights.sample(point, &mut sampler).unwrap();
+            let ray = Ray::new(point, sample.direction);
+            let mut record = HitRecord::default();
+            if !world.hit(&ray, 1e-9, f64::INFINITY, &mut record) {
+                assert!(sample.distance.is_infinite());
+                assert_close(sample.pdf, lights.environment_pdf(point, sample.direction));
+                misses += 1;
+            } else if record.material.is_emissive() && (record.t - sample.distance).abs() < 1e-6 {
+                assert_close(sample.pdf, lights.surface_pdf(&ray, &record));
+                surfaces += 1;
+            }
+        }
+        assert!(surfaces > 500 && misses > 500);
+    }
+
+    #[test]
+    fn black_environment_is_not_a_light() {
+        let world = HittableList::default();
+        let environment = Arc::new(SolidEnvironment {
+            color: Color::default(),
+        });
+        let lights = LightList::new(&world, environment);
+        assert!(lights.is_empty());
+        let mut sampler = IndependentSampler::new(1);
+        assert!(lights.sample(Point3::default(), &mut sampler).is_none());
+    }
+}
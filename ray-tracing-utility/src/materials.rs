@@ -1,13 +1,47 @@
 use crate::*;
+use std::f64::consts::PI;
 use std::sync::Arc;
-pub trait Material {
+pub trait Material: Send + Sync {
     fn scatter(
         &self,
         ray_in: &Ray,
         record: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool;
+
+    /// Light arriving from the unit vector `direction` that leaves along
+    /// `ray_in` reversed, as a share per unit of incoming radiance: the
+    /// BSDF times the cosine at the surface. Returned with the density of
+    /// [`scatter`](Material::scatter) picking `direction`, per unit solid
+    /// angle. `None` for materials that only scatter into a few directions,
+    /// like mirrors and glass, which sampling lights can't help.
+    fn scattering(
+        &self,
+        _ray_in: &Ray,
+        _record: &HitRecord,
+        _direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        None
+    }
+
+    /// Light given off at surface coordinates `(u, v)` and point `point`;
+    /// none unless the material is a light.
+    fn emitted(&self, _u: f64, _v: f64, _point: &Point3) -> Color {
+        Color::default()
+    }
+
+    /// Whether surfaces of this material give off light, and so are worth
+    /// sampling as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Scene file description of this material, if it has one.
+    fn describe(&self) -> Option<MaterialDescription> {
+        None
+    }
 }
 #[derive(Copy, Clone)]
 pub struct Lambertian {
@@ -33,8 +67,9 @@ impl Material for Lambertian {
         record: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let mut scatter_direction = record.normal + Vec3::random_unit_vec();
+        let mut scatter_direction = record.normal + sample_unit_sphere(sampler.get_2d());
         if scatter_direction.near_zero() {
             scatter_direction = record.normal;
         }
@@ -45,6 +80,23 @@ impl Material for Lambertian {
         *attenuation = self.albedo;
         true
     }
+
+    fn scattering(
+        &self,
+        _ray_in: &Ray,
+        record: &HitRecord,
+        direction: Vec3,
+    ) -> Option<(Color, f64)> {
+        // `scatter` picks directions in proportion to the cosine
+        let cosine = record.normal.dot(direction).max(0.) / PI;
+        Some((self.albedo * cosine, cosine))
+    }
+
+    fn describe(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::Lambertian {
+            albedo: self.albedo.into(),
+        })
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -73,14 +125,22 @@ impl Material for Metal {
         record: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         let reflected = ray_in.direction.reflect(record.normal).unit();
         *scattered = Ray {
             origin: record.point,
-            direction: reflected + self.fuzz * Vec3::random_in_unit_sphere(),
+            direction: reflected + self.fuzz * sample_unit_ball(sampler.get_2d(), sampler.get_1d()),
         };
         *attenuation = self.albedo;
-        return scattered.direction.dot(record.normal) > 0.;
+        scattered.direction.dot(record.normal) > 0.
+    }
+
+    fn describe(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::Metal {
+            albedo: self.albedo.into(),
+            fuzz: self.fuzz,
+        })
     }
 }
 
@@ -102,7 +162,7 @@ impl Dielectric {
         // Use Schlick's approximation for reflectance.
         let mut r0 = (1. - refraction_index) / (1. + refraction_index);
         r0 = r0 * r0;
-        return r0 + (1. - r0) * (1. - cosine).powi(5);
+        r0 + (1. - r0) * (1. - cosine).powi(5)
     }
 }
 
@@ -113,6 +173,7 @@ impl Material for Dielectric {
         record: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         *attenuation = Color::new(1., 1., 1.);
         let refraction_ratio = if record.front_face {
@@ -129,7 +190,7 @@ impl Material for Dielectric {
         let cannot_refract = refraction_ratio * sin_theta > 1.;
 
         let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::random::<f64>()
+            || Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
         {
             unit_direction.reflect(record.normal)
         } else {
@@ -140,6 +201,56 @@ impl Material for Dielectric {
             origin: record.point,
             direction,
         };
-        return true;
+        true
+    }
+
+    fn describe(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::Dielectric {
+            refraction_index: self.refraction_index,
+        })
+    }
+}
+
+/// Emits the same light in every direction from both sides of a surface
+/// and scatters nothing.
+#[derive(Copy, Clone)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+
+    pub fn as_ref(self) -> Arc<Self> {
+        Arc::new(self)
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _record: &HitRecord,
+        _attenuation: &mut Color,
+        _scattered: &mut Ray,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, _point: &Point3) -> Color {
+        self.emit
+    }
+
+    fn is_emissive(&self) -> bool {
+        <[f64; 3]>::from(self.emit) != [0.; 3]
+    }
+
+    fn describe(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::DiffuseLight {
+            emit: self.emit.into(),
+        })
     }
 }
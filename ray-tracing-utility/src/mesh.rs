@@ -0,0 +1,193 @@
+use crate::*;
+use std::sync::Arc;
+
+/// Indexed triangle mesh. Vertex attributes are stored once and shared by
+/// every face that references them, and faces are found through a
+/// [`FlatBvh`] over the face indices rather than one object per triangle.
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<[f64; 2]>,
+    colors: Vec<Color>,
+    indices: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material>,
+    bvh: FlatBvh,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        indices: Vec<[u32; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(
+            indices
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < positions.len()),
+            "TriangleMesh index out of range"
+        );
+        let bounds = indices
+            .iter()
+            .map(|&[i0, i1, i2]| {
+                triangle_box(
+                    positions[i0 as usize],
+                    positions[i1 as usize],
+                    positions[i2 as usize],
+                )
+            })
+            .collect::<Vec<_>>();
+        TriangleMesh {
+            bvh: BvhBuilder::default().build(&bounds),
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            indices,
+            material,
+        }
+    }
+
+    /// Per-vertex normals, one for each position.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(normals.len(), self.positions.len(), "one normal per vertex");
+        self.normals = normals;
+        self
+    }
+
+    /// Per-vertex texture coordinates, one for each position.
+    pub fn with_uvs(mut self, uvs: Vec<[f64; 2]>) -> Self {
+        assert_eq!(uvs.len(), self.positions.len(), "one uv per vertex");
+        self.uvs = uvs;
+        self
+    }
+
+    /// Per-vertex colours, one for each position.
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Self {
+        assert_eq!(colors.len(), self.positions.len(), "one color per vertex");
+        self.colors = colors;
+        self
+    }
+
+    pub fn positions(&self) -> &[Point3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[[f64; 2]] {
+        &self.uvs
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.indices
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        self.bvh.stats()
+    }
+
+    fn face(&self, face: usize) -> (usize, usize, usize) {
+        let [i0, i1, i2] = self.indices[face];
+        (i0 as usize, i1 as usize, i2 as usize)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool {
+        let mut closest: Option<(usize, f64, Vec3)> = None;
+        self.bvh
+            .traverse(ray, t_min, t_max, |face, closest_so_far| {
+                let (i0, i1, i2) = self.face(face);
+                let p = &self.positions;
+                intersect_triangle(ray, p[i0], p[i1], p[i2], t_min, closest_so_far).map(
+                    |(t, barycentric)| {
+                        closest = Some((face, t, barycentric));
+                        t
+                    },
+                )
+            });
+        let (face, t, b) = match closest {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let (i0, i1, i2) = self.face(face);
+        let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+        record.t = t;
+        record.point = b.x * p0 + b.y * p1 + b.z * p2;
+        record.barycentric = b;
+        record.set_face_normal(ray, (p1 - p0).cross(p2 - p0).unit());
+        if !self.normals.is_empty() {
+            let n = &self.normals;
+            record.set_shading_normal(b.x * n[i0] + b.y * n[i1] + b.z * n[i2]);
+        }
+        if self.uvs.is_empty() {
+            record.u = b.y;
+            record.v = b.z;
+        } else {
+            let uv = &self.uvs;
+            record.u = b.x * uv[i0][0] + b.y * uv[i1][0] + b.z * uv[i2][0];
+            record.v = b.x * uv[i0][1] + b.y * uv[i1][1] + b.z * uv[i2][1];
+        }
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bvh.bounding_box() {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.bvh.any_hit(ray, t_min, t_max, |face, t_max| {
+            let (i0, i1, i2) = self.face(face);
+            let p = &self.positions;
+            intersect_triangle(ray, p[i0], p[i1], p[i2], t_min, t_max).is_some()
+        })
+    }
+
+    fn describe(&self, exporter: &mut SceneExporter) -> bool {
+        let to_arrays = |vs: &[Vec3]| vs.iter().map(|&v| v.into()).collect::<Vec<_>>();
+        match exporter.material(&self.material) {
+            Some(material) => {
+                exporter.add(ObjectDescription::Mesh {
+                    positions: to_arrays(&self.positions),
+                    indices: self.indices.clone(),
+                    normals: to_arrays(&self.normals),
+                    uvs: self.uvs.clone(),
+                    colors: to_arrays(&self.colors),
+                    material,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn add_lights(&self, lights: &mut LightCollector) {
+        if !self.material.is_emissive() {
+            return;
+        }
+        for face in 0..self.face_count() {
+            let (i0, i1, i2) = self.face(face);
+            let p = &self.positions;
+            lights.add(&self.material, LightShape::Triangle([p[i0], p[i1], p[i2]]));
+        }
+    }
+}
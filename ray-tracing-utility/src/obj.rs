@@ -0,0 +1,582 @@
+use crate::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{error, fmt, fs, io};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Material parsed from an MTL file. Only the parameters that map onto
+/// this renderer's materials are kept.
+#[derive(Clone)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// `Kd`
+    pub diffuse: Color,
+    /// `Ks`
+    pub specular: Color,
+    /// `Ke`
+    pub emission: Color,
+    /// `Ns`
+    pub shininess: f64,
+    /// `Ni`
+    pub refraction_index: f64,
+    /// `d`, or `1 - Tr`
+    pub dissolve: f64,
+    pub illum: u32,
+}
+
+impl MtlMaterial {
+    pub fn new(name: &str) -> Self {
+        MtlMaterial {
+            name: name.to_string(),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::default(),
+            emission: Color::default(),
+            shininess: 0.,
+            refraction_index: 1.,
+            dissolve: 1.,
+            illum: 2,
+        }
+    }
+
+    /// Picks the closest material this renderer has:
+    /// materials with a `Ke` become `DiffuseLight`, transparent materials
+    /// become `Dielectric`, materials whose specular colour outweighs the
+    /// diffuse one become `Metal` with a fuzz derived from `Ns`, and
+    /// everything else is `Lambertian`.
+    pub fn to_material(&self) -> Arc<dyn Material> {
+        let max = |c: Color| c.x.max(c.y).max(c.z);
+        let transparent = self.dissolve < 1. || matches!(self.illum, 4 | 6 | 7 | 9);
+        if max(self.emission) > 0. {
+            DiffuseLight::new(self.emission).as_ref()
+        } else if transparent {
+            let refraction_index = if self.refraction_index > 1. {
+                self.refraction_index
+            } else {
+                1.5
+            };
+            Dielectric::new(refraction_index).as_ref()
+        } else if max(self.specular) > max(self.diffuse) {
+            Metal {
+                albedo: self.specular,
+                fuzz: (2. / (self.shininess.max(0.) + 2.)).sqrt().min(1.),
+            }
+            .as_ref()
+        } else {
+            Lambertian {
+                albedo: self.diffuse,
+            }
+            .as_ref()
+        }
+    }
+}
+
+pub struct ObjMesh {
+    /// Name of the `o`/`g` group the faces were in.
+    pub name: String,
+    pub material: Option<String>,
+    pub mesh: TriangleMesh,
+}
+
+/// Contents of an OBJ file, split into one mesh per group and material.
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: HashMap<String, MtlMaterial>,
+}
+
+impl ObjModel {
+    pub fn into_hittable_list(self) -> HittableList {
+        let mut list = HittableList::default();
+        for mesh in self.meshes {
+            list.add(Arc::new(mesh.mesh));
+        }
+        list
+    }
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = read_to_string(path)?;
+    parse_obj(&source, path)
+}
+
+/// Parses OBJ source. `path` is used in error messages and to resolve
+/// `mtllib` files relative to the OBJ file.
+pub fn parse_obj(source: &str, path: &Path) -> Result<ObjModel, ObjError> {
+    let mut parser = ObjParser {
+        path,
+        line: 0,
+        positions: Vec::new(),
+        uvs: Vec::new(),
+        normals: Vec::new(),
+        materials: HashMap::new(),
+        material_cache: HashMap::new(),
+        group: String::from("default"),
+        material: None,
+        smoothing: 0,
+        faces: Vec::new(),
+        meshes: Vec::new(),
+    };
+    for (line, statement) in logical_lines(source) {
+        parser.line = line;
+        parser.statement(statement)?;
+    }
+    parser.flush();
+    Ok(ObjModel {
+        meshes: parser.meshes,
+        materials: parser.materials,
+    })
+}
+
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let path = path.as_ref();
+    let source = read_to_string(path)?;
+    parse_mtl(&source, path)
+}
+
+pub fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+    for (line, statement) in logical_lines(source) {
+        let error = |message: String| parse_error(path, line, message);
+        let mut words = statement.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args = words.collect::<Vec<_>>();
+
+        if keyword == "newmtl" {
+            if let Some(material) = current.take() {
+                materials.insert(material.name.clone(), material);
+            }
+            if args.is_empty() {
+                return Err(error(String::from("newmtl without a name")));
+            }
+            current = Some(MtlMaterial::new(&args.join(" ")));
+            continue;
+        }
+
+        let material = match (&mut current, keyword) {
+            (Some(material), _) => material,
+            (None, "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "illum") => {
+                return Err(error(format!("'{}' before any newmtl", keyword)))
+            }
+            (None, _) => continue,
+        };
+        match keyword {
+            "Kd" => material.diffuse = parse_color(&args).map_err(error)?,
+            "Ks" => material.specular = parse_color(&args).map_err(error)?,
+            "Ke" => material.emission = parse_color(&args).map_err(error)?,
+            "Ns" => material.shininess = parse_single(keyword, &args).map_err(error)?,
+            "Ni" => material.refraction_index = parse_single(keyword, &args).map_err(error)?,
+            "d" => {
+                // `d -halo 0.5` is a variant some exporters write
+                let args = if args.first() == Some(&"-halo") {
+                    &args[1..]
+                } else {
+                    &args[..]
+                };
+                material.dissolve = parse_single(keyword, args).map_err(error)?;
+            }
+            "Tr" => material.dissolve = 1. - parse_single::<f64>(keyword, &args).map_err(error)?,
+            "illum" => material.illum = parse_single(keyword, &args).map_err(error)?,
+            _ => {}
+        }
+    }
+    if let Some(material) = current.take() {
+        materials.insert(material.name.clone(), material);
+    }
+    Ok(materials)
+}
+
+fn read_to_string(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn parse_error(path: &Path, line: usize, message: String) -> ObjError {
+    ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    }
+}
+
+/// Strips comments and joins `\` continuations, yielding each statement with
+/// the line number it starts on.
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut statements = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (i, raw) in source.lines().enumerate() {
+        let text = match raw.find('#') {
+            Some(comment) => &raw[..comment],
+            None => raw,
+        };
+        let (text, continued) = match text.trim_end().strip_suffix('\\') {
+            Some(text) => (text, true),
+            None => (text, false),
+        };
+        let (line, mut statement) = pending.take().unwrap_or((i + 1, String::new()));
+        statement.push(' ');
+        statement.push_str(text);
+        if continued {
+            pending = Some((line, statement));
+        } else {
+            statements.push((line, statement));
+        }
+    }
+    if let Some(statement) = pending {
+        statements.push(statement);
+    }
+    statements
+}
+
+fn parse_number<T: std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse::<T>()
+        .map_err(|_| format!("invalid number '{}'", word))
+}
+
+fn parse_single<T: std::str::FromStr>(keyword: &str, args: &[&str]) -> Result<T, String> {
+    match args {
+        [word] => parse_number(word),
+        _ => Err(format!("'{}' expects one value", keyword)),
+    }
+}
+
+fn parse_color(args: &[&str]) -> Result<Color, String> {
+    match args {
+        [r] => {
+            let r = parse_number(r)?;
+            Ok(Color::new(r, r, r))
+        }
+        [r, g, b] => Ok(Color::new(
+            parse_number(r)?,
+            parse_number(g)?,
+            parse_number(b)?,
+        )),
+        [kind, ..] if *kind == "spectral" || *kind == "xyz" => {
+            Err(format!("'{}' colors are not supported", kind))
+        }
+        _ => Err(String::from("expected 1 or 3 color components")),
+    }
+}
+
+/// A face corner as absolute, zero-based indices into the OBJ attributes.
+#[derive(Copy, Clone)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct Face {
+    corners: Vec<Corner>,
+    smoothing: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum NormalKey {
+    None,
+    Given(usize),
+    Smooth(u32),
+    Flat(usize),
+}
+
+struct ObjParser<'a> {
+    path: &'a Path,
+    line: usize,
+    positions: Vec<Point3>,
+    uvs: Vec<[f64; 2]>,
+    normals: Vec<Vec3>,
+    materials: HashMap<String, MtlMaterial>,
+    material_cache: HashMap<String, Arc<dyn Material>>,
+    group: String,
+    material: Option<String>,
+    smoothing: u32,
+    faces: Vec<Face>,
+    meshes: Vec<ObjMesh>,
+}
+
+impl<'a> ObjParser<'a> {
+    fn error(&self, message: String) -> ObjError {
+        parse_error(self.path, self.line, message)
+    }
+
+    fn floats(
+        &self,
+        keyword: &str,
+        args: &[&str],
+        min: usize,
+        max: usize,
+    ) -> Result<Vec<f64>, ObjError> {
+        if args.len() < min || args.len() > max {
+            return Err(self.error(format!(
+                "'{}' expects {} to {} values, got {}",
+                keyword,
+                min,
+                max,
+                args.len()
+            )));
+        }
+        args.iter()
+            .map(|word| parse_number(word).map_err(|message| self.error(message)))
+            .collect()
+    }
+
+    fn statement(&mut self, statement: String) -> Result<(), ObjError> {
+        let mut words = statement.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => return Ok(()),
+        };
+        let args = words.collect::<Vec<_>>();
+        match keyword {
+            "v" => {
+                // some exporters append a vertex colour after xyz
+                let v = self.floats(keyword, &args, 3, 7)?;
+                self.positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = self.floats(keyword, &args, 1, 3)?;
+                self.uvs.push([v[0], v.get(1).copied().unwrap_or(0.)]);
+            }
+            "vn" => {
+                let v = self.floats(keyword, &args, 3, 3)?;
+                self.normals.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(self.error(format!(
+                        "face needs at least 3 vertices, got {}",
+                        args.len()
+                    )));
+                }
+                let corners = args
+                    .iter()
+                    .map(|word| self.corner(word))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.faces.push(Face {
+                    corners,
+                    smoothing: self.smoothing,
+                });
+            }
+            "o" | "g" => {
+                self.flush();
+                self.group = if args.is_empty() {
+                    String::from("default")
+                } else {
+                    args.join(" ")
+                };
+            }
+            "usemtl" => {
+                if args.is_empty() {
+                    return Err(self.error(String::from("usemtl without a name")));
+                }
+                self.flush();
+                self.material = Some(args.join(" "));
+            }
+            "s" => {
+                self.smoothing = match args.as_slice() {
+                    ["off"] => 0,
+                    [group] => parse_number(group).map_err(|message| self.error(message))?,
+                    _ => return Err(self.error(String::from("'s' expects one value"))),
+                };
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(self.error(String::from("mtllib without a file name")));
+                }
+                let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+                for file in args {
+                    let materials = load_mtl(dir.join(file)).map_err(|e| match e {
+                        ObjError::Io { path, source } => self.error(format!(
+                            "cannot read material library {}: {}",
+                            path.display(),
+                            source
+                        )),
+                        e => e,
+                    })?;
+                    self.materials.extend(materials);
+                }
+            }
+            // lines, points, curves and surfaces aren't renderable
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn corner(&self, word: &str) -> Result<Corner, ObjError> {
+        let mut parts = word.split('/');
+        let position = parts.next().unwrap_or("");
+        let uv = parts.next().filter(|s| !s.is_empty());
+        let normal = parts.next().filter(|s| !s.is_empty());
+        if parts.next().is_some() {
+            return Err(self.error(format!("invalid face vertex '{}'", word)));
+        }
+        Ok(Corner {
+            position: self.index(position, self.positions.len(), "vertex")?,
+            uv: uv
+                .map(|i| self.index(i, self.uvs.len(), "texture coordinate"))
+                .transpose()?,
+            normal: normal
+                .map(|i| self.index(i, self.normals.len(), "normal"))
+                .transpose()?,
+        })
+    }
+
+    /// Resolves a one-based or negative (relative to the end) OBJ index.
+    fn index(&self, word: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+        let i: i64 = parse_number(word).map_err(|message| self.error(message))?;
+        let resolved = match i {
+            0 => None,
+            i if i > 0 => Some(i - 1),
+            i => Some(count as i64 + i),
+        };
+        match resolved {
+            Some(r) if r >= 0 && (r as usize) < count => Ok(r as usize),
+            _ => Err(self.error(format!(
+                "{} index {} out of range ({} defined)",
+                what, i, count
+            ))),
+        }
+    }
+
+    /// Material for the current `usemtl`. Names missing from the material
+    /// libraries fall back to the default grey, like most OBJ readers do.
+    fn material(&mut self) -> Arc<dyn Material> {
+        let materials = &self.materials;
+        let mtl = match self.material.as_ref().and_then(|name| materials.get(name)) {
+            Some(mtl) => mtl,
+            None => return Lambertian::new(204, 204, 204).as_ref(),
+        };
+        self.material_cache
+            .entry(mtl.name.clone())
+            .or_insert_with(|| mtl.to_material())
+            .clone()
+    }
+
+    /// Turns the faces gathered so far into a mesh.
+    fn flush(&mut self) {
+        if self.faces.is_empty() {
+            return;
+        }
+        let faces = std::mem::take(&mut self.faces);
+
+        let mut triangles = Vec::new();
+        for (f, face) in faces.iter().enumerate() {
+            // fan triangulation, polygons are assumed convex
+            for i in 1..face.corners.len() - 1 {
+                triangles.push((
+                    [face.corners[0], face.corners[i], face.corners[i + 1]],
+                    face.smoothing,
+                    f,
+                ));
+            }
+        }
+        let face_normal = |corners: &[Corner; 3]| {
+            let p = |i: usize| self.positions[corners[i].position];
+            (p(1) - p(0)).cross(p(2) - p(0))
+        };
+
+        let has_normals = faces
+            .iter()
+            .any(|f| f.smoothing != 0 || f.corners.iter().any(|c| c.normal.is_some()));
+        let has_uvs = faces
+            .iter()
+            .any(|f| f.corners.iter().any(|c| c.uv.is_some()));
+
+        // area-weighted normals per position and smoothing group
+        let mut smooth_normals: HashMap<(usize, u32), Vec3> = HashMap::new();
+        for (corners, smoothing, _) in triangles.iter() {
+            if *smoothing == 0 {
+                continue;
+            }
+            let n = face_normal(corners);
+            for c in corners.iter().filter(|c| c.normal.is_none()) {
+                *smooth_normals.entry((c.position, *smoothing)).or_default() += n;
+            }
+        }
+
+        let mut vertices: HashMap<(usize, Option<usize>, NormalKey), u32> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::with_capacity(triangles.len());
+        for (corners, smoothing, f) in triangles.iter() {
+            let mut face = [0u32; 3];
+            for (slot, c) in face.iter_mut().zip(corners.iter()) {
+                let key = match c.normal {
+                    Some(n) => NormalKey::Given(n),
+                    None if !has_normals => NormalKey::None,
+                    None if *smoothing != 0 => NormalKey::Smooth(*smoothing),
+                    None => NormalKey::Flat(*f),
+                };
+                *slot = *vertices.entry((c.position, c.uv, key)).or_insert_with(|| {
+                    positions.push(self.positions[c.position]);
+                    if has_uvs {
+                        uvs.push(c.uv.map_or([0., 0.], |i| self.uvs[i]));
+                    }
+                    match key {
+                        NormalKey::None => {}
+                        NormalKey::Given(n) => normals.push(self.normals[n]),
+                        NormalKey::Smooth(s) => normals.push(smooth_normals[&(c.position, s)]),
+                        NormalKey::Flat(_) => normals.push(face_normal(corners)),
+                    }
+                    (positions.len() - 1) as u32
+                });
+            }
+            indices.push(face);
+        }
+
+        let material = self.material();
+        let mut mesh = TriangleMesh::new(positions, indices, material);
+        if has_normals {
+            mesh = mesh.with_normals(normals);
+        }
+        if has_uvs {
+            mesh = mesh.with_uvs(uvs);
+        }
+        self.meshes.push(ObjMesh {
+            name: self.group.clone(),
+            material: self.material.clone(),
+            mesh,
+        });
+    }
+}
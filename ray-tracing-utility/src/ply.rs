@@ -0,0 +1,424 @@
+use crate::*;
+use std::path::Path;
+use std::sync::Arc;
+use std::{error, fmt, fs, io};
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    Header { line: usize, message: String },
+    Data(String),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io(e) => write!(f, "ply: {}", e),
+            PlyError::Header { line, message } => {
+                write!(f, "ply header, line {}: {}", line, message)
+            }
+            PlyError::Data(message) => write!(f, "ply data: {}", message),
+        }
+    }
+}
+
+impl error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PlyError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlyError {
+    fn from(e: io::Error) -> Self {
+        PlyError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+pub fn load_ply<P: AsRef<Path>>(
+    path: P,
+    material: Arc<dyn Material>,
+) -> Result<TriangleMesh, PlyError> {
+    let bytes = fs::read(path)?;
+    parse_ply(&bytes, material)
+}
+
+/// Reads an ASCII or binary PLY file into a triangle mesh. Polygons are fan
+/// triangulated. Vertex normals (`nx`, `ny`, `nz`), texture coordinates
+/// (`u`/`v`, `s`/`t` or `texture_u`/`texture_v`) and colours
+/// (`red`, `green`, `blue`) are kept when the file has them.
+pub fn parse_ply(bytes: &[u8], material: Arc<dyn Material>) -> Result<TriangleMesh, PlyError> {
+    let (header, body) = parse_header(bytes)?;
+    let mut reader = match header.format {
+        Format::Ascii => DataReader::Ascii(AsciiReader::new(body)?),
+        Format::BinaryLittleEndian => DataReader::Binary(body, false),
+        Format::BinaryBigEndian => DataReader::Binary(body, true),
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices: Vec<[u32; 3]> = Vec::new();
+
+    for element in header.elements.iter() {
+        match element.name.as_str() {
+            "vertex" => {
+                let find = |names: &[&str]| {
+                    element.properties.iter().position(|p| match p {
+                        Property::Scalar(name, _) => names.contains(&name.as_str()),
+                        Property::List(..) => false,
+                    })
+                };
+                let xyz = [find(&["x"]), find(&["y"]), find(&["z"])];
+                let nxyz = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+                let uv = [
+                    find(&["u", "s", "texture_u", "texture_s"]),
+                    find(&["v", "t", "texture_v", "texture_t"]),
+                ];
+                let rgb = [
+                    find(&["red", "r", "diffuse_red"]),
+                    find(&["green", "g", "diffuse_green"]),
+                    find(&["blue", "b", "diffuse_blue"]),
+                ];
+                let xyz = match xyz {
+                    [Some(x), Some(y), Some(z)] => [x, y, z],
+                    _ => return Err(PlyError::Data(String::from("vertex has no x, y, z"))),
+                };
+                let nxyz = match nxyz {
+                    [Some(x), Some(y), Some(z)] => Some([x, y, z]),
+                    _ => None,
+                };
+                let uv = match uv {
+                    [Some(u), Some(v)] => Some([u, v]),
+                    _ => None,
+                };
+                let rgb = match rgb {
+                    [Some(r), Some(g), Some(b)] => Some([r, g, b]),
+                    _ => None,
+                };
+                // integer colours are 0-255, float ones 0-1
+                let color_scale = rgb.map_or(1., |[r, ..]| match &element.properties[r] {
+                    Property::Scalar(_, Scalar::F32) | Property::Scalar(_, Scalar::F64) => 1.,
+                    Property::Scalar(_, Scalar::U16) => 1. / 65535.,
+                    _ => 1. / 255.,
+                });
+
+                let mut values = vec![0.; element.properties.len()];
+                for _ in 0..element.count {
+                    for (value, property) in values.iter_mut().zip(element.properties.iter()) {
+                        match property {
+                            Property::Scalar(_, ty) => *value = reader.read(*ty)?,
+                            Property::List(_, count_ty, item_ty) => {
+                                reader.skip_list(*count_ty, *item_ty)?
+                            }
+                        }
+                    }
+                    let v3 = |[x, y, z]: [usize; 3]| Vec3::new(values[x], values[y], values[z]);
+                    positions.push(v3(xyz));
+                    if let Some(n) = nxyz {
+                        normals.push(v3(n));
+                    }
+                    if let Some([u, v]) = uv {
+                        uvs.push([values[u], values[v]]);
+                    }
+                    if let Some(c) = rgb {
+                        colors.push(v3(c) * color_scale);
+                    }
+                }
+            }
+            "face" => {
+                let list = element.properties.iter().position(|p| match p {
+                    Property::List(name, _, _) => {
+                        name == "vertex_indices" || name == "vertex_index"
+                    }
+                    Property::Scalar(..) => false,
+                });
+                let list = match list {
+                    Some(list) => list,
+                    None => return Err(PlyError::Data(String::from("face has no vertex_indices"))),
+                };
+                for face in 0..element.count {
+                    let mut polygon = Vec::new();
+                    for (i, property) in element.properties.iter().enumerate() {
+                        match property {
+                            Property::Scalar(_, ty) => {
+                                reader.read(*ty)?;
+                            }
+                            Property::List(_, count_ty, item_ty) if i == list => {
+                                let count = reader.read(*count_ty)? as usize;
+                                for _ in 0..count {
+                                    polygon.push(reader.read(*item_ty)?);
+                                }
+                            }
+                            Property::List(_, count_ty, item_ty) => {
+                                reader.skip_list(*count_ty, *item_ty)?
+                            }
+                        }
+                    }
+                    if polygon.len() < 3 {
+                        return Err(PlyError::Data(format!(
+                            "face {} has {} vertices",
+                            face,
+                            polygon.len()
+                        )));
+                    }
+                    let mut corners = Vec::with_capacity(polygon.len());
+                    for &index in polygon.iter() {
+                        // ASCII indices are parsed as any number, including nan
+                        if index.fract() != 0. {
+                            return Err(PlyError::Data(format!(
+                                "face {} has vertex index '{}', which is not an integer",
+                                face, index
+                            )));
+                        }
+                        if index < 0. || index as usize >= positions.len() {
+                            return Err(PlyError::Data(format!(
+                                "face {} refers to vertex {}, but there are {}",
+                                face,
+                                index,
+                                positions.len()
+                            )));
+                        }
+                        corners.push(index as u32);
+                    }
+                    for i in 1..corners.len() - 1 {
+                        indices.push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in element.properties.iter() {
+                        match property {
+                            Property::Scalar(_, ty) => {
+                                reader.read(*ty)?;
+                            }
+                            Property::List(_, count_ty, item_ty) => {
+                                reader.skip_list(*count_ty, *item_ty)?
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut mesh = TriangleMesh::new(positions, indices, material);
+    if !normals.is_empty() {
+        mesh = mesh.with_normals(normals);
+    }
+    if !uvs.is_empty() {
+        mesh = mesh.with_uvs(uvs);
+    }
+    if !colors.is_empty() {
+        mesh = mesh.with_colors(colors);
+    }
+    Ok(mesh)
+}
+
+fn parse_header(bytes: &[u8]) -> Result<(Header, &[u8]), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut rest = bytes;
+    let mut line = 0;
+    loop {
+        line += 1;
+        let error = |message: String| PlyError::Header { line, message };
+        let end = match rest.iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None => return Err(error(String::from("missing end_header"))),
+        };
+        let text = std::str::from_utf8(&rest[..end])
+            .map_err(|_| error(String::from("header is not text")))?
+            .trim_end_matches('\r');
+        rest = &rest[end + 1..];
+
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        if line == 1 {
+            if words != ["ply"] {
+                return Err(error(String::from("not a ply file")));
+            }
+            continue;
+        }
+        match words.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", kind, "1.0"] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(format!("unknown format '{}'", kind))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("invalid element count '{}'", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => {
+                let scalar = |ty: &str| {
+                    Scalar::parse(ty).ok_or_else(|| error(format!("unknown type '{}'", ty)))
+                };
+                let property =
+                    Property::List(name.to_string(), scalar(count_ty)?, scalar(item_ty)?);
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(error(String::from("property before any element"))),
+                }
+            }
+            ["property", ty, name] => {
+                let ty =
+                    Scalar::parse(ty).ok_or_else(|| error(format!("unknown type '{}'", ty)))?;
+                match elements.last_mut() {
+                    Some(element) => element
+                        .properties
+                        .push(Property::Scalar(name.to_string(), ty)),
+                    None => return Err(error(String::from("property before any element"))),
+                }
+            }
+            ["end_header"] => break,
+            _ => return Err(error(format!("unexpected '{}'", text))),
+        }
+    }
+    match format {
+        Some(format) => Ok((Header { format, elements }, rest)),
+        None => Err(PlyError::Header {
+            line,
+            message: String::from("missing format line"),
+        }),
+    }
+}
+
+struct AsciiReader<'a> {
+    tokens: std::vec::IntoIter<(usize, &'a str)>,
+}
+
+impl<'a> AsciiReader<'a> {
+    fn new(body: &'a [u8]) -> Result<Self, PlyError> {
+        let text = std::str::from_utf8(body)
+            .map_err(|_| PlyError::Data(String::from("ascii body is not text")))?;
+        let tokens = text
+            .lines()
+            .enumerate()
+            .flat_map(|(i, line)| line.split_whitespace().map(move |token| (i + 1, token)))
+            .collect::<Vec<_>>();
+        Ok(AsciiReader {
+            tokens: tokens.into_iter(),
+        })
+    }
+}
+
+enum DataReader<'a> {
+    Ascii(AsciiReader<'a>),
+    /// Remaining bytes, and whether they are big endian.
+    Binary(&'a [u8], bool),
+}
+
+impl<'a> DataReader<'a> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, PlyError> {
+        match self {
+            DataReader::Ascii(reader) => match reader.tokens.next() {
+                Some((line, token)) => token.parse::<f64>().map_err(|_| {
+                    PlyError::Data(format!("body line {}: invalid number '{}'", line, token))
+                }),
+                None => Err(PlyError::Data(String::from("unexpected end of data"))),
+            },
+            DataReader::Binary(bytes, big_endian) => {
+                let size = ty.size();
+                if bytes.len() < size {
+                    return Err(PlyError::Data(String::from("unexpected end of data")));
+                }
+                let mut buf = [0u8; 8];
+                buf[..size].copy_from_slice(&bytes[..size]);
+                if *big_endian {
+                    buf[..size].reverse();
+                }
+                *bytes = &bytes[size..];
+                let [b0, b1, b2, b3, ..] = buf;
+                Ok(match ty {
+                    Scalar::I8 => b0 as i8 as f64,
+                    Scalar::U8 => b0 as f64,
+                    Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+
+    fn skip_list(&mut self, count_ty: Scalar, item_ty: Scalar) -> Result<(), PlyError> {
+        let count = self.read(count_ty)? as usize;
+        for _ in 0..count {
+            self.read(item_ty)?;
+        }
+        Ok(())
+    }
+}
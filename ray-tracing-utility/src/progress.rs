@@ -0,0 +1,190 @@
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Number of rays traced, by kind.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RayStats {
+    /// Rays from the camera, one per sample.
+    pub primary: u64,
+    /// Rays scattered off a surface.
+    pub secondary: u64,
+    /// Rays that only check whether a light is visible.
+    pub shadow: u64,
+}
+
+impl RayStats {
+    pub fn total(&self) -> u64 {
+        self.primary + self.secondary + self.shadow
+    }
+}
+
+impl AddAssign for RayStats {
+    fn add_assign(&mut self, other: Self) {
+        self.primary += other.primary;
+        self.secondary += other.secondary;
+        self.shadow += other.shadow;
+    }
+}
+
+/// How far a render has got.
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    /// Samples traced so far, out of the `samples_total` the render needs
+    /// to reach `samples_per_pixel` everywhere.
+    pub samples_done: u64,
+    pub samples_total: u64,
+    pub elapsed: Duration,
+    pub time_limit: Option<Duration>,
+    pub rays: RayStats,
+    /// Set in the last report of a render, which may have stopped before
+    /// tracing every sample.
+    pub finished: bool,
+}
+
+impl Progress {
+    /// Finished share of the render, from 0 to 1. Measured against the
+    /// time limit when that comes first.
+    pub fn fraction(&self) -> f64 {
+        if self.finished || self.samples_total == 0 {
+            return 1.;
+        }
+        let samples = self.samples_done as f64 / self.samples_total as f64;
+        match self.time_limit {
+            Some(limit) => samples.max(self.elapsed.as_secs_f64() / limit.as_secs_f64()),
+            None => samples,
+        }
+        .min(1.)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished
+    }
+
+    /// Time left if the rest of the samples go as fast as the ones already
+    /// traced, or until the time limit if that is sooner; unknown until the
+    /// first samples are done.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.finished {
+            return Some(Duration::ZERO);
+        }
+        let time_left = self
+            .time_limit
+            .map(|limit| limit.saturating_sub(self.elapsed));
+        if self.samples_done == 0 {
+            return time_left;
+        }
+        let left =
+            self.samples_total.saturating_sub(self.samples_done) as f64 / self.samples_done as f64;
+        let samples_left = self.elapsed.mul_f64(left);
+        Some(time_left.map_or(samples_left, |time_left| time_left.min(samples_left)))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0. {
+            self.rays.total() as f64 / seconds
+        } else {
+            0.
+        }
+    }
+}
+
+/// Prints [`Progress`] to stderr at most once per interval, and a summary of
+/// the traced rays once the render is done. Meant as a
+/// [`Renderer::on_progress`](crate::Renderer::on_progress) hook; it can be
+/// called from any number of threads without locking.
+pub struct ProgressReporter {
+    interval: Duration,
+    // `Progress::elapsed` of the last line printed, in milliseconds
+    last_report: AtomicU64,
+    finished: AtomicBool,
+}
+
+impl Default for ProgressReporter {
+    fn default() -> Self {
+        ProgressReporter::new(Duration::from_millis(250))
+    }
+}
+
+impl ProgressReporter {
+    pub fn new(interval: Duration) -> Self {
+        ProgressReporter {
+            interval,
+            last_report: AtomicU64::new(0),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    pub fn report(&self, progress: &Progress) {
+        if progress.is_done() {
+            if !self.finished.swap(true, Ordering::Relaxed) {
+                eprintln!("\r{}", status_line(progress));
+                eprintln!("{}", summary(progress));
+            }
+            return;
+        }
+
+        let now = progress.elapsed.as_millis() as u64;
+        let last = self.last_report.load(Ordering::Relaxed);
+        if now < last + self.interval.as_millis() as u64 || self.finished.load(Ordering::Relaxed) {
+            return;
+        }
+        // only the thread that moves the clock on prints
+        if self
+            .last_report
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            eprint!("\r{}", status_line(progress));
+        }
+    }
+}
+
+fn status_line(progress: &Progress) -> String {
+    let eta = match progress.eta() {
+        Some(eta) => format_duration(eta),
+        None => "--:--".into(),
+    };
+    format!(
+        "{:5.1}%  elapsed {}  ETA {}  {} rays/s   ",
+        progress.fraction() * 100.,
+        format_duration(progress.elapsed),
+        eta,
+        format_count(progress.rays_per_second()),
+    )
+}
+
+fn summary(progress: &Progress) -> String {
+    let rays = &progress.rays;
+    format!(
+        "Rays: {} primary, {} secondary, {} shadow, {} total in {:.1}s ({} rays/s)",
+        rays.primary,
+        rays.secondary,
+        rays.shadow,
+        rays.total(),
+        progress.elapsed.as_secs_f64(),
+        format_count(progress.rays_per_second()),
+    )
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour up.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// A count with a metric prefix, e.g. `12.3M`.
+fn format_count(count: f64) -> String {
+    match count {
+        c if c >= 1e9 => format!("{:.2}G", c / 1e9),
+        c if c >= 1e6 => format!("{:.2}M", c / 1e6),
+        c if c >= 1e3 => format!("{:.2}k", c / 1e3),
+        c => format!("{:.0}", c),
+    }
+}
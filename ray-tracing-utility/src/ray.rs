@@ -1,5 +1,5 @@
 use crate::*;
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
@@ -14,28 +14,75 @@ impl Ray {
         self.origin + self.direction * t
     }
 
-    pub fn calc_color(self, hittable: &dyn Hittable, depth: i32) -> Color {
-        // println!("depth: {}", depth);
-        if depth <= 0 {
-            return Color::from((0, 0, 0));
-        }
-        let mut record = HitRecord::default();
-        if hittable.hit(&self, 0.01, std::f64::INFINITY, &mut record) {
-            // let target = record.point + record.normal + Vec3::random_unit_vec();
+    /// Radiance arriving along the ray: the light emitted by what it hits
+    /// plus what that surface scatters towards it, or the environment's.
+    ///
+    /// At every hit on a surface that isn't a mirror or glass, one of
+    /// `lights` is sampled and a shadow ray checks that nothing is in its
+    /// way. Light found that way and light the scattered ray runs into are
+    /// weighted by multiple importance sampling, so small bright lights
+    /// don't need a lucky bounce to be seen.
+    pub fn calc_color(
+        self,
+        hittable: &dyn Hittable,
+        environment: &dyn Environment,
+        lights: &LightList,
+        depth: i32,
+        sampler: &mut dyn Sampler,
+        stats: &mut RayStats,
+    ) -> Color {
+        let mut radiance = Color::default();
+        let mut throughput = Color::new(1., 1., 1.);
+        let mut ray = self;
+        // density the last bounce picked `ray` with, unless it was a mirror
+        // or glass bounce that light sampling couldn't have made
+        let mut scatter_pdf = None;
+        for bounce in 0..depth.max(0) {
+            if bounce > 0 {
+                stats.secondary += 1;
+            }
+            let mut record = HitRecord::default();
+            if !hittable.hit(&ray, 0.01, f64::INFINITY, &mut record) {
+                let weight = scatter_pdf.map_or(1., |pdf| {
+                    power_heuristic(
+                        pdf,
+                        lights.environment_pdf(ray.origin, ray.direction.unit()),
+                    )
+                });
+                radiance += throughput * environment.radiance(ray.direction) * weight;
+                break;
+            }
+
+            let emitted = record.material.emitted(record.u, record.v, &record.point);
+            let weight = scatter_pdf.map_or(1., |pdf| {
+                power_heuristic(pdf, lights.surface_pdf(&ray, &record))
+            });
+            radiance += throughput * emitted * weight;
+
+            // the last vertex can't trace the scattered ray that would find
+            // the other, MIS-weighted share of its direct light, so it gets
+            // none, as if the path had stopped a bounce earlier
+            if bounce + 1 < depth {
+                radiance +=
+                    throughput * direct_light(&ray, &record, hittable, lights, sampler, stats);
+            }
+
             let mut scattered = Ray::default();
             let mut attenuation = Color::default();
-            if record
+            if !record
                 .material
-                .scatter(&self, &record, &mut attenuation, &mut scattered)
+                .scatter(&ray, &record, &mut attenuation, &mut scattered, sampler)
             {
-                return attenuation * scattered.calc_color(hittable, depth - 1);
-            } else {
-                return Color::default();
+                break;
             }
+            scatter_pdf = record
+                .material
+                .scattering(&ray, &record, scattered.direction.unit())
+                .map(|(_, pdf)| pdf);
+            throughput = throughput * attenuation;
+            ray = scattered;
         }
-        let unit = self.direction.unit();
-        let t = 0.5 * (unit.y + 1f64);
-        return Color::from((1, 1, 1)) * (1f64 - t) + Color::from((0.5f64, 0.7f64, 1f64)) * t;
+        radiance
     }
 
     pub fn hit(&self, center: &Point3, radius: f64) -> f64 {
@@ -43,15 +90,6 @@ impl Ray {
     }
 }
 
-impl Default for Ray {
-    fn default() -> Self {
-        Ray {
-            origin: Point3::default(),
-            direction: Vec3::default(),
-        }
-    }
-}
-
 fn hit_sphere(&center: &Point3, radius: f64, ray: &Ray) -> f64 {
     let oc = ray.origin - center;
     let a = ray.direction.length_squared();
@@ -64,3 +102,54 @@ fn hit_sphere(&center: &Point3, radius: f64, ray: &Ray) -> f64 {
         (-half_b - discriminant.sqrt()) / a
     }
 }
+
+/// Light reaching the hit described by `record` straight from one of
+/// `lights`, scattered back along `ray`.
+fn direct_light(
+    ray: &Ray,
+    record: &HitRecord,
+    hittable: &dyn Hittable,
+    lights: &LightList,
+    sampler: &mut dyn Sampler,
+    stats: &mut RayStats,
+) -> Color {
+    if lights.is_empty()
+        || record
+            .material
+            .scattering(ray, record, record.normal)
+            .is_none()
+    {
+        return Color::default();
+    }
+    let sample = match lights.sample(record.point, sampler) {
+        Some(sample) => sample,
+        None => return Color::default(),
+    };
+    let (scattering, scatter_pdf) = match record.material.scattering(ray, record, sample.direction)
+    {
+        Some(scattering) => scattering,
+        None => return Color::default(),
+    };
+    let contribution = scattering * sample.radiance;
+    if <[f64; 3]>::from(contribution) == [0.; 3] {
+        return Color::default();
+    }
+    // stop short of the light so it doesn't shadow itself
+    stats.shadow += 1;
+    let shadow = Ray::new(record.point, sample.direction);
+    if hittable.occluded(&shadow, 0.01, sample.distance - 0.01) {
+        return Color::default();
+    }
+    contribution * (power_heuristic(sample.pdf, scatter_pdf) / sample.pdf)
+}
+
+/// Veach's power heuristic with an exponent of 2: the weight of a sample
+/// taken with density `pdf` when `other_pdf` could also have taken it.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0. {
+        0.
+    } else {
+        a / (a + b)
+    }
+}
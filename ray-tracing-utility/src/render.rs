@@ -0,0 +1,307 @@
+use crate::*;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+type ProgressHook<'a> = Box<dyn Fn(&Progress) + Sync + 'a>;
+type TileHook<'a> = Box<dyn Fn(&Tile, &Mutex<Film>) + Sync + 'a>;
+type PassHook<'a> = Box<dyn Fn(&Film) + Sync + 'a>;
+
+/// Traces a world through a camera into a [`Film`], using the image size,
+/// sample count and bounce limit of the render settings.
+pub struct Renderer<'a> {
+    world: &'a dyn Hittable,
+    camera: &'a Camera,
+    background: Arc<dyn Environment>,
+    // built on first use, once the background is final
+    lights: OnceLock<LightList>,
+    settings: RenderSettings,
+    on_progress: Option<ProgressHook<'a>>,
+    on_tile: Option<TileHook<'a>>,
+    on_pass: Option<PassHook<'a>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(world: &'a dyn Hittable, camera: &'a Camera, settings: RenderSettings) -> Self {
+        Renderer {
+            world,
+            camera,
+            background: Arc::new(GradientEnvironment::sky()),
+            lights: OnceLock::new(),
+            settings,
+            on_progress: None,
+            on_tile: None,
+            on_pass: None,
+        }
+    }
+
+    /// What rays that miss the world see; the book's sky unless set.
+    pub fn background(mut self, background: Arc<dyn Environment>) -> Self {
+        self.background = background;
+        self.lights = OnceLock::new();
+        self
+    }
+
+    /// The emissive surfaces of the world and the background, which are
+    /// sampled for direct lighting.
+    pub fn lights(&self) -> &LightList {
+        self.lights
+            .get_or_init(|| LightList::new(self.world, self.background.clone()))
+    }
+
+    /// Calls `hook` from the render threads each time a tile is done, and
+    /// once more when the render is.
+    pub fn on_progress(mut self, hook: impl Fn(&Progress) + Sync + 'a) -> Self {
+        self.on_progress = Some(Box::new(hook));
+        self
+    }
+
+    /// Calls `hook` with every finished tile and the film it was just added
+    /// to, e.g. to show or save the partial image. The film is unlocked
+    /// during the call, and calls from different threads may overlap; lock
+    /// it only for as long as it takes to look at it, as the render threads
+    /// wait for it.
+    pub fn on_tile(mut self, hook: impl Fn(&Tile, &Mutex<Film>) + Sync + 'a) -> Self {
+        self.on_tile = Some(Box::new(hook));
+        self
+    }
+
+    /// Calls `hook` with the film after every pass, e.g. to write an
+    /// intermediate image.
+    pub fn on_pass(mut self, hook: impl Fn(&Film) + Sync + 'a) -> Self {
+        self.on_pass = Some(Box::new(hook));
+        self
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// A fresh sampler of the kind and seed picked in the render settings.
+    /// Its values only depend on the pixel and sample it is asked for, so
+    /// every thread can have its own.
+    pub fn sampler(&self) -> Box<dyn Sampler> {
+        self.settings
+            .sampler
+            .build(self.settings.samples_per_pixel, self.settings.seed)
+    }
+
+    /// Traces the samples with the given indices through pixel `(x, y)`,
+    /// counted from the top left, and returns their sums. Each ray goes
+    /// through a different point of the pixel's square, which is what
+    /// anti-aliases edges. The traced rays are counted in `stats`.
+    pub fn sample_pixel(
+        &self,
+        x: usize,
+        y: usize,
+        samples: Range<u32>,
+        sampler: &mut dyn Sampler,
+        stats: &mut RayStats,
+    ) -> SampleSum {
+        let width = self.settings.width as f64;
+        let height = self.settings.height() as f64;
+        // the camera's t runs from the bottom of the image up
+        let row = height - 1. - y as f64;
+        let lights = self.lights();
+        let mut sum = SampleSum::default();
+        for index in samples {
+            sampler.start_pixel_sample(x as u32, y as u32, index);
+            let [dx, dy] = sampler.get_2d();
+            let s = (x as f64 + dx) / width;
+            let t = (row + dy) / height;
+            stats.primary += 1;
+            sum.add(self.camera.get_ray(s, t, sampler).calc_color(
+                self.world,
+                self.background.as_ref(),
+                lights,
+                self.settings.max_depth as i32,
+                sampler,
+                stats,
+            ));
+        }
+        sum
+    }
+
+    /// The image split into tiles of the size and order picked in the render
+    /// settings.
+    pub fn tiles(&self) -> Vec<Tile> {
+        tiles(
+            self.settings.width as usize,
+            self.settings.height() as usize,
+            self.settings.tile_size as usize,
+            self.settings.tile_order,
+        )
+    }
+
+    /// Renders the image until it has `samples_per_pixel` samples per pixel,
+    /// the time limit is up or the noise is below its target.
+    pub fn render(&self) -> Film {
+        let width = self.settings.width as usize;
+        let height = self.settings.height() as usize;
+        self.render_from(Film::new(width, height))
+    }
+
+    /// Carries on with a film that already has samples, e.g. one loaded from
+    /// a checkpoint. Every pixel continues at the first sample index it
+    /// hasn't had, so without a time limit or noise target the result is the
+    /// image a single render would have made. A render stopped early by
+    /// either has only some of the samples the stratified and Sobol samplers
+    /// spread over a pixel, so those are less evenly spread than at the full
+    /// count.
+    ///
+    /// Samples are added in passes of `samples_per_pass` samples per pixel,
+    /// after each of which the targets are checked. Within a pass the tiles
+    /// are queued on the rayon pool in tile order; idle threads take the next
+    /// one, so the image fills in roughly in that order.
+    pub fn render_from(&self, film: Film) -> Film {
+        let target = self.settings.samples_per_pixel;
+        let per_pass = match self.settings.samples_per_pass {
+            0 => target,
+            n => n,
+        };
+        let counters = Counters::new(samples_missing(&film, target), self.settings.time_limit);
+        let deadline = counters.time_limit.map(|limit| counters.start + limit);
+        let tiles = self.tiles();
+
+        let mut film = Mutex::new(film);
+        loop {
+            let current = film.get_mut().unwrap();
+            let fewest = pixels(current)
+                .map(|(x, y)| current.sample_count(x, y))
+                .min()
+                .unwrap_or(target);
+            let noise_reached = self
+                .settings
+                .noise_target
+                .is_some_and(|noise| current.noise() <= noise);
+            if fewest >= target || deadline.is_some_and(|d| Instant::now() >= d) || noise_reached {
+                break;
+            }
+
+            // take every pixel up to the next multiple of the pass size
+            let pass_target = ((fewest / per_pass + 1) * per_pass).min(target);
+            rayon::scope_fifo(|scope| {
+                for tile in tiles.iter() {
+                    let (film, counters) = (&film, &counters);
+                    scope.spawn_fifo(move |_| {
+                        self.render_tile(tile, pass_target, deadline, film, counters)
+                    });
+                }
+            });
+            if let Some(hook) = &self.on_pass {
+                hook(film.get_mut().unwrap());
+            }
+        }
+
+        if let Some(hook) = &self.on_progress {
+            hook(&counters.progress(true));
+        }
+        film.into_inner().unwrap()
+    }
+
+    /// Brings every pixel of `tile` up to `target` samples, adds them to
+    /// `film` and reports the tile. Does nothing once `deadline` has passed.
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        target: u32,
+        deadline: Option<Instant>,
+        film: &Mutex<Film>,
+        counters: &Counters,
+    ) {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return;
+        }
+        let first = {
+            let film = film.lock().unwrap();
+            tile.pixels()
+                .map(|(x, y)| film.sample_count(x, y).min(target))
+                .collect::<Vec<_>>()
+        };
+        if first.iter().all(|&first| first == target) {
+            return;
+        }
+        let mut sampler = self.sampler();
+        let mut stats = RayStats::default();
+        let sums = tile
+            .pixels()
+            .zip(&first)
+            .map(|((x, y), &first)| {
+                self.sample_pixel(x, y, first..target, sampler.as_mut(), &mut stats)
+            })
+            .collect::<Vec<_>>();
+
+        let mut locked = film.lock().unwrap();
+        for ((x, y), sum) in tile.pixels().zip(sums) {
+            locked.add_samples(x, y, sum);
+        }
+        drop(locked);
+        if let Some(hook) = &self.on_tile {
+            hook(tile, film);
+        }
+
+        counters.add(&stats);
+        if let Some(hook) = &self.on_progress {
+            hook(&counters.progress(false));
+        }
+    }
+}
+
+fn pixels(film: &Film) -> impl Iterator<Item = (usize, usize)> {
+    let width = film.width();
+    (0..film.height()).flat_map(move |y| (0..width).map(move |x| (x, y)))
+}
+
+/// Samples still to trace to give every pixel `target` of them.
+fn samples_missing(film: &Film, target: u32) -> u64 {
+    pixels(film)
+        .map(|(x, y)| target.saturating_sub(film.sample_count(x, y)) as u64)
+        .sum()
+}
+
+/// Progress of a render, shared by its threads without a lock.
+struct Counters {
+    start: Instant,
+    time_limit: Option<Duration>,
+    samples_total: u64,
+    primary: AtomicU64,
+    secondary: AtomicU64,
+    shadow: AtomicU64,
+}
+
+impl Counters {
+    fn new(samples_total: u64, time_limit: Option<f64>) -> Self {
+        Counters {
+            start: Instant::now(),
+            time_limit: time_limit.map(Duration::from_secs_f64),
+            samples_total,
+            primary: AtomicU64::new(0),
+            secondary: AtomicU64::new(0),
+            shadow: AtomicU64::new(0),
+        }
+    }
+
+    fn add(&self, stats: &RayStats) {
+        self.primary.fetch_add(stats.primary, Ordering::Relaxed);
+        self.secondary.fetch_add(stats.secondary, Ordering::Relaxed);
+        self.shadow.fetch_add(stats.shadow, Ordering::Relaxed);
+    }
+
+    fn progress(&self, finished: bool) -> Progress {
+        let rays = RayStats {
+            primary: self.primary.load(Ordering::Relaxed),
+            secondary: self.secondary.load(Ordering::Relaxed),
+            shadow: self.shadow.load(Ordering::Relaxed),
+        };
+        Progress {
+            // every sample starts with one primary ray
+            samples_done: rays.primary,
+            samples_total: self.samples_total,
+            elapsed: self.start.elapsed(),
+            time_limit: self.time_limit,
+            rays,
+            finished,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::*;
+
+const PCG32_MULTIPLIER: u64 = 0x5851_f42d_4c95_7f2d;
+
+/// Melissa O'Neill's PCG32 (XSH RR) generator. It is small, fast and, unlike
+/// the thread-local generator, fully determined by its seed, so anything
+/// drawn from it is reproducible.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    /// A generator on stream `stream` starting at `seed`. Generators on
+    /// different streams give independent sequences even for equal seeds.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(PCG32_MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    /// Uniform value in `[0, 1)` with the full 53 bits of precision.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1. / (1u64 << 53) as f64)
+    }
+
+    pub fn next_f64_in_range(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.next_f64()
+    }
+
+    pub fn next_vec3(&mut self) -> Vec3 {
+        Vec3::new(self.next_f64(), self.next_f64(), self.next_f64())
+    }
+
+    pub fn next_vec3_in_range(&mut self, low: f64, high: f64) -> Vec3 {
+        Vec3::new(
+            self.next_f64_in_range(low, high),
+            self.next_f64_in_range(low, high),
+            self.next_f64_in_range(low, high),
+        )
+    }
+}
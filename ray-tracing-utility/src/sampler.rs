@@ -0,0 +1,421 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+/// Source of the sample values in `[0, 1)` that drive one path: the pixel
+/// position, the lens position and every scattering decision. Values are
+/// drawn as consecutive dimensions of a sample, so a low-discrepancy
+/// sequence can spread each dimension evenly over a pixel's samples.
+pub trait Sampler {
+    /// Starts sample `index` of pixel `(x, y)`, going back to its first
+    /// dimension.
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> [f64; 2];
+}
+
+/// Which [`Sampler`] a render uses.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    /// Uniform random values, no stratification at all.
+    #[default]
+    Independent,
+    /// One jittered stratum per sample in every dimension.
+    Stratified,
+    /// The Halton sequence with Owen-scrambled digits.
+    Halton,
+    /// The first two Sobol dimensions with Owen scrambling, shuffled
+    /// independently for every pair of dimensions.
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Whether the sampler lays its samples out for the number of samples
+    /// per pixel, so that samples made for one count don't fit with those
+    /// of another.
+    pub fn depends_on_sample_count(self) -> bool {
+        matches!(self, SamplerKind::Stratified | SamplerKind::Sobol)
+    }
+
+    /// A sampler for `samples_per_pixel` samples per pixel. Different seeds
+    /// give differently scrambled sequences.
+    pub fn build(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(samples_per_pixel, seed)),
+        }
+    }
+}
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            seed,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.rng = pixel_sample_rng(x, y, index, self.seed);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.rng.next_f64(), self.rng.next_f64()]
+    }
+}
+
+/// Random numbers for one sample of one pixel. Every sample gets its own
+/// stream, so its values don't depend on which thread traces it or on what
+/// was traced before.
+fn pixel_sample_rng(x: u32, y: u32, index: u32, seed: u64) -> Pcg32 {
+    let pixel = hash(&[x as u64, y as u64, seed]);
+    Pcg32::new(hash(&[index as u64, pixel]), pixel)
+}
+
+/// Where a sampler is in the current pixel sample; shared by the samplers
+/// that hash it into per-dimension permutations.
+#[derive(Copy, Clone, Default)]
+struct SampleState {
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SampleState {
+    fn start(&mut self, x: u32, y: u32, index: u32) {
+        *self = SampleState {
+            x,
+            y,
+            index,
+            dimension: 0,
+        };
+    }
+
+    /// Hash of the pixel and the next dimension, which it then claims.
+    fn next_hash(&mut self, seed: u64) -> u64 {
+        let h = hash(&[self.x as u64, self.y as u64, self.dimension as u64, seed]);
+        self.dimension += 1;
+        h
+    }
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    // 2D strata grid, `columns * rows == samples_per_pixel`
+    columns: u32,
+    seed: u64,
+    state: SampleState,
+    // jitter within the strata
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        // the most square grid that uses every sample
+        let columns = (1..=(samples_per_pixel as f64).sqrt() as u32)
+            .rev()
+            .find(|&c| samples_per_pixel.is_multiple_of(c))
+            .unwrap_or(1);
+        StratifiedSampler {
+            samples_per_pixel,
+            columns,
+            seed,
+            state: SampleState::default(),
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+
+    /// Stratum of the current sample in the next dimension. Every dimension
+    /// visits the strata in its own order so they don't correlate.
+    fn stratum(&mut self) -> Option<u32> {
+        let h = self.state.next_hash(self.seed);
+        if self.state.index < self.samples_per_pixel {
+            Some(permutation_element(
+                self.state.index,
+                self.samples_per_pixel,
+                h as u32,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.state.start(x, y, index);
+        self.rng = pixel_sample_rng(x, y, index, self.seed);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        match self.stratum() {
+            Some(s) => (s as f64 + self.rng.next_f64()) / self.samples_per_pixel as f64,
+            None => self.rng.next_f64(),
+        }
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        match self.stratum() {
+            Some(s) => {
+                let rows = self.samples_per_pixel / self.columns;
+                [
+                    ((s % self.columns) as f64 + self.rng.next_f64()) / self.columns as f64,
+                    ((s / self.columns) as f64 + self.rng.next_f64()) / rows as f64,
+                ]
+            }
+            None => [self.rng.next_f64(), self.rng.next_f64()],
+        }
+    }
+}
+
+pub struct HaltonSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        HaltonSampler {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let primes = primes();
+        // past the last prime, start over with the well distributed bases
+        let base = primes[self.state.dimension as usize % primes.len()];
+        let h = self.state.next_hash(self.seed);
+        owen_scrambled_radical_inverse(base, self.state.index as u64, h as u32)
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.get_1d(), self.get_1d()]
+    }
+}
+
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    state: SampleState,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        SobolSampler {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            state: SampleState::default(),
+        }
+    }
+
+    /// Sample index shuffled for the next dimension pair, and the hash
+    /// that seeds its scrambling.
+    fn next_index(&mut self) -> (u32, u64) {
+        let h = self.state.next_hash(self.seed);
+        let index = if self.state.index < self.samples_per_pixel {
+            permutation_element(self.state.index, self.samples_per_pixel, h as u32)
+        } else {
+            self.state.index
+        };
+        (index, h)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, h) = self.next_index();
+        to_unit(fast_owen_scramble(index.reverse_bits(), (h >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let (index, h) = self.next_index();
+        [
+            to_unit(fast_owen_scramble(index.reverse_bits(), h as u32)),
+            to_unit(fast_owen_scramble(sobol_second(index), (h >> 32) as u32)),
+        ]
+    }
+}
+
+/// The largest f64 below 1.
+pub const ONE_MINUS_EPSILON: f64 = 1. - f64::EPSILON / 2.;
+
+fn to_unit(bits: u32) -> f64 {
+    (bits as f64 / 4294967296.).min(ONE_MINUS_EPSILON)
+}
+
+/// Second dimension of the Sobol sequence, as 32 fixed-point bits.
+fn sobol_second(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut v = 1 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+/// Hash-based nested uniform scrambling of 32 fixed-point bits (Burley,
+/// "Practical Hash-based Owen Scrambling").
+fn fast_owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+/// Radical inverse of `a` in `base`, with every digit permuted by a hash
+/// of the digits before it, which is Owen scrambling for any base.
+fn owen_scrambled_radical_inverse(base: u32, mut a: u64, hash: u32) -> f64 {
+    let base_f = base as f64;
+    let inv_base = 1. / base_f;
+    let mut inv_base_m = 1.;
+    let mut value = 0.;
+    // scrambled digits so far, only used as hash input so it may wrap
+    let mut prefix: u64 = 0;
+    // keep going until further digits no longer change the result
+    while 1. - (base_f - 1.) * inv_base_m < 1. {
+        let next = a / base as u64;
+        let digit = (a - next * base as u64) as u32;
+        let digit_hash = mix_bits(hash as u64 ^ prefix) as u32;
+        let digit = permutation_element(digit, base, digit_hash);
+        prefix = prefix.wrapping_mul(base as u64).wrapping_add(digit as u64);
+        inv_base_m *= inv_base;
+        value += digit as f64 * inv_base_m;
+        a = next;
+    }
+    value.min(ONE_MINUS_EPSILON)
+}
+
+/// The first thousand primes, the bases of the Halton dimensions.
+fn primes() -> &'static [u32] {
+    static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+    PRIMES.get_or_init(|| {
+        let mut primes: Vec<u32> = Vec::with_capacity(1000);
+        let mut n = 2;
+        while primes.len() < 1000 {
+            if primes
+                .iter()
+                .take_while(|&&p| p * p <= n)
+                .all(|&p| n % p != 0)
+            {
+                primes.push(n);
+            }
+            n += 1;
+        }
+        primes
+    })
+}
+
+/// Finalizer of a 64-bit hash; spreads every input bit over the output.
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e3779b97f4a7c15, |h, &v| mix_bits(h ^ mix_bits(v)))
+}
+
+/// Element `i` of a random permutation of `0..len` picked by `p`, without
+/// storing the permutation (Kensler, "Correlated Multi-Jittered Sampling").
+pub fn permutation_element(mut i: u32, len: u32, p: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % len
+}
+
+/// Uniformly distributed direction.
+pub fn sample_unit_sphere(u: [f64; 2]) -> Vec3 {
+    let z = 1. - 2. * u[0];
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniformly distributed point inside the unit sphere; `u` picks the
+/// direction and `r` the distance from the centre.
+pub fn sample_unit_ball(u: [f64; 2], r: f64) -> Vec3 {
+    sample_unit_sphere(u) * r.cbrt()
+}
+
+/// Uniformly distributed point of the unit disk in the xy plane, using
+/// Shirley's concentric mapping so strata stay compact.
+pub fn sample_unit_disk(u: [f64; 2]) -> Vec3 {
+    let (a, b) = (2. * u[0] - 1., 2. * u[1] - 1.);
+    if a == 0. && b == 0. {
+        return Vec3::default();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4. * (b / a))
+    } else {
+        (b, PI / 2. - PI / 4. * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.)
+}
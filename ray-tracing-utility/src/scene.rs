@@ -0,0 +1,930 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{error, fmt, fs, io};
+
+/// A scene file: render settings, camera, background, named materials and
+/// objects, stored as JSON. Mesh paths are relative to the scene file.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub render: RenderSettings,
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub background: BackgroundDescription,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    pub objects: Vec<ObjectDescription>,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub aspect_ratio: f64,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    /// Samples added to every pixel before the time limit and noise target
+    /// are checked again; 0 takes all `samples_per_pixel` in one pass.
+    pub samples_per_pass: u32,
+    /// Seconds after which the render stops, however many samples it has.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_limit: Option<f64>,
+    /// Noise level, as the mean relative standard error of the pixels (see
+    /// [`Film::noise`]), at which the render stops.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise_target: Option<f64>,
+    pub sampler: SamplerKind,
+    /// Seeds every random decision of the render; the same seed gives the
+    /// same image no matter how many threads trace it.
+    pub seed: u64,
+    /// Width and height of the square tiles the image is rendered in.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    pub tone_mapping: ToneMapping,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 1200,
+            aspect_ratio: 3. / 2.,
+            samples_per_pixel: 500,
+            max_depth: 100,
+            samples_per_pass: 16,
+            time_limit: None,
+            noise_target: None,
+            sampler: SamplerKind::default(),
+            seed: 0,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn height(&self) -> u32 {
+        (self.width as f64 / self.aspect_ratio) as u32
+    }
+
+    /// Checks that the settings can be rendered.
+    pub fn validate(&self) -> Result<(), InvalidSetting> {
+        if self.width == 0 {
+            return Err(InvalidSetting::new("render.width", "must be positive"));
+        }
+        if self.aspect_ratio <= 0. || self.height() == 0 {
+            return Err(InvalidSetting::new(
+                "render.aspect_ratio",
+                "must give a positive image height",
+            ));
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(InvalidSetting::new(
+                "render.samples_per_pixel",
+                "must be positive",
+            ));
+        }
+        if let Some(seconds) = self.time_limit {
+            if !(seconds.is_finite() && seconds > 0.) {
+                return Err(InvalidSetting::new(
+                    "render.time_limit",
+                    "must be a positive number of seconds",
+                ));
+            }
+        }
+        if let Some(noise) = self.noise_target {
+            if !(noise.is_finite() && noise > 0.) {
+                return Err(InvalidSetting::new(
+                    "render.noise_target",
+                    "must be positive",
+                ));
+            }
+        }
+        if self.tile_size == 0 {
+            return Err(InvalidSetting::new("render.tile_size", "must be positive"));
+        }
+        let tone_mapping = &self.tone_mapping;
+        if !tone_mapping.exposure.is_finite() {
+            return Err(InvalidSetting::new(
+                "render.tone_mapping.exposure",
+                "must be a finite number of stops",
+            ));
+        }
+        if let Some(kelvin) = tone_mapping.white_balance {
+            if !(1667. ..=25000.).contains(&kelvin) {
+                return Err(InvalidSetting::new(
+                    "render.tone_mapping.white_balance",
+                    "must be between 1667 and 25000 Kelvin",
+                ));
+            }
+        }
+        if let ToneMapOperator::ExtendedReinhard { white } = tone_mapping.operator {
+            if white <= 0. {
+                return Err(InvalidSetting::new(
+                    "render.tone_mapping.operator.extended_reinhard.white",
+                    "must be positive",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A render setting with a value that can't be rendered, found by
+/// [`RenderSettings::validate`].
+#[derive(Debug)]
+pub struct InvalidSetting {
+    /// Where the setting is in a scene file, e.g. `render.width`.
+    pub key: &'static str,
+    pub message: &'static str,
+}
+
+impl InvalidSetting {
+    fn new(key: &'static str, message: &'static str) -> Self {
+        InvalidSetting { key, message }
+    }
+}
+
+impl fmt::Display for InvalidSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+impl error::Error for InvalidSetting {}
+
+/// Arguments of [`Camera::new`]; the aspect ratio comes from the render
+/// settings.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub look_from: [f64; 3],
+    pub look_at: [f64; 3],
+    #[serde(default = "default_vup")]
+    pub vup: [f64; 3],
+    /// vertical field-of-view in degrees
+    pub v_fov: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    /// defaults to the distance from `look_from` to `look_at`
+    #[serde(default)]
+    pub focus_dist: Option<f64>,
+}
+
+fn default_vup() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+/// What rays that miss every object see. Image paths are relative to the
+/// scene file and must be Radiance `.hdr` images; `rotation` is in degrees
+/// about the x, y and z axes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum BackgroundDescription {
+    /// The book's sky, white below and light blue above.
+    #[default]
+    Sky,
+    /// No light at all, for scenes lit only by their emissive materials.
+    Black,
+    /// The same colour in every direction.
+    Solid([f64; 3]),
+    Gradient {
+        bottom: [f64; 3],
+        top: [f64; 3],
+        #[serde(default, skip_serializing_if = "is_zero_rotation")]
+        rotation: [f64; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    /// A latitude-longitude image, centred on -z.
+    Equirect {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "is_zero_rotation")]
+        rotation: [f64; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    /// Cube faces in the order +x, -x, +y, -y, +z, -z.
+    CubeMap {
+        faces: [PathBuf; 6],
+        #[serde(default, skip_serializing_if = "is_zero_rotation")]
+        rotation: [f64; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    /// Preetham's daylight sky with the sun in it; radiance is in kcd/m².
+    PhysicalSky {
+        sun: SunDescription,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+/// Where the sun of a physical sky is: either its elevation and azimuth in
+/// degrees, azimuth clockwise from north along -z, or a place and a local
+/// time to work them out from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SunDescription {
+    Angles {
+        elevation: f64,
+        azimuth: f64,
+    },
+    Location {
+        /// Degrees north.
+        latitude: f64,
+        /// Degrees east.
+        longitude: f64,
+        year: i32,
+        month: u32,
+        day: u32,
+        /// Local time as a fraction of a day in hours, e.g. 14.5 for 2:30pm.
+        hour: f64,
+        /// Hours the local time is ahead of UTC.
+        #[serde(default)]
+        utc_offset: f64,
+    },
+}
+
+impl SunDescription {
+    pub fn position(&self) -> SunPosition {
+        match *self {
+            SunDescription::Angles { elevation, azimuth } => SunPosition { elevation, azimuth },
+            SunDescription::Location {
+                latitude,
+                longitude,
+                year,
+                month,
+                day,
+                hour,
+                utc_offset,
+            } => SunPosition::at(latitude, longitude, year, month, day, hour - utc_offset),
+        }
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn is_zero_rotation(rotation: &[f64; 3]) -> bool {
+    *rotation == [0.; 3]
+}
+
+fn default_intensity() -> f64 {
+    1.
+}
+
+fn default_turbidity() -> f64 {
+    3.
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Lambertian { albedo: [f64; 3] },
+    Metal { albedo: [f64; 3], fuzz: f64 },
+    Dielectric { refraction_index: f64 },
+    DiffuseLight { emit: [f64; 3] },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        normals: Option<[[f64; 3]; 3]>,
+        material: String,
+    },
+    /// Indexed triangle mesh stored inline; the vertex attribute lists are
+    /// either empty or have one entry per position.
+    Mesh {
+        positions: Vec<[f64; 3]>,
+        indices: Vec<[u32; 3]>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normals: Vec<[f64; 3]>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        uvs: Vec<[f64; 2]>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        colors: Vec<[f64; 3]>,
+        material: String,
+    },
+    /// Wavefront OBJ; uses the materials of its MTL files unless
+    /// `material` overrides them.
+    Obj {
+        path: PathBuf,
+        #[serde(default)]
+        material: Option<String>,
+    },
+    Ply {
+        path: PathBuf,
+        material: String,
+    },
+    Stl {
+        path: PathBuf,
+        material: String,
+        #[serde(default)]
+        weld_tolerance: Option<f64>,
+        #[serde(default)]
+        crease_angle: Option<f64>,
+    },
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Malformed JSON, or a missing, unknown or mistyped key.
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// Well-formed file with a value that doesn't make sense, found at `key`.
+    Invalid {
+        path: PathBuf,
+        key: String,
+        message: String,
+    },
+    /// The scene holds an object or material the format can't describe.
+    Export(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+            SceneError::Invalid { path, key, message } => {
+                write!(f, "{}: {}: {}", path.display(), key, message)
+            }
+            SceneError::Export(message) => write!(f, "scene export: {}", message),
+        }
+    }
+}
+
+impl error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A scene ready to render.
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: Camera,
+    pub background: Arc<dyn Environment>,
+    pub settings: RenderSettings,
+}
+
+impl Scene {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&source, path)
+    }
+
+    /// Parses scene JSON. `path` is used in error messages and to resolve
+    /// mesh files.
+    pub fn parse(source: &str, path: &Path) -> Result<Self, SceneError> {
+        let description: SceneDescription = serde_json::from_str(source).map_err(|e| {
+            // the location is reported separately
+            let location = format!(" at line {} column {}", e.line(), e.column());
+            let message = e.to_string();
+            SceneError::Parse {
+                path: path.to_path_buf(),
+                line: e.line(),
+                column: e.column(),
+                message: message.trim_end_matches(&location).to_string(),
+            }
+        })?;
+        description.build(path)
+    }
+}
+
+impl Scene {
+    pub fn describe(&self) -> Result<SceneDescription, SceneError> {
+        SceneDescription::from_scene(
+            &self.world,
+            &self.camera,
+            self.background.as_ref(),
+            self.settings,
+        )
+    }
+}
+
+impl MaterialDescription {
+    pub fn build(&self) -> Arc<dyn Material> {
+        match *self {
+            MaterialDescription::Lambertian { albedo } => Lambertian {
+                albedo: Color::from(albedo),
+            }
+            .as_ref(),
+            MaterialDescription::Metal { albedo, fuzz } => Metal {
+                albedo: Color::from(albedo),
+                fuzz,
+            }
+            .as_ref(),
+            MaterialDescription::Dielectric { refraction_index } => {
+                Dielectric::new(refraction_index).as_ref()
+            }
+            MaterialDescription::DiffuseLight { emit } => {
+                DiffuseLight::new(Color::from(emit)).as_ref()
+            }
+        }
+    }
+}
+
+impl BackgroundDescription {
+    /// Builds the environment, loading its images relative to `dir`.
+    pub fn build(&self, dir: &Path) -> io::Result<Arc<dyn Environment>> {
+        Ok(match self {
+            BackgroundDescription::Sky => Arc::new(GradientEnvironment::sky()),
+            BackgroundDescription::Black => Arc::new(SolidEnvironment {
+                color: Color::default(),
+            }),
+            BackgroundDescription::Solid(color) => Arc::new(SolidEnvironment {
+                color: Color::from(*color),
+            }),
+            BackgroundDescription::Gradient {
+                bottom,
+                top,
+                rotation,
+                intensity,
+            } => Arc::new(GradientEnvironment {
+                bottom: Color::from(*bottom),
+                top: Color::from(*top),
+                transform: EnvironmentTransform::new(*rotation, *intensity),
+            }),
+            BackgroundDescription::Equirect {
+                path,
+                rotation,
+                intensity,
+            } => Arc::new(EquirectEnvironment::load(
+                dir.join(path),
+                EnvironmentTransform::new(*rotation, *intensity),
+            )?),
+            BackgroundDescription::CubeMap {
+                faces,
+                rotation,
+                intensity,
+            } => Arc::new(CubeMapEnvironment::load(
+                faces.clone().map(|face| dir.join(face)),
+                EnvironmentTransform::new(*rotation, *intensity),
+            )?),
+            BackgroundDescription::PhysicalSky {
+                sun,
+                turbidity,
+                intensity,
+            } => Arc::new(PreethamSky::new(sun.position(), *turbidity, *intensity)),
+        })
+    }
+}
+
+impl CameraDescription {
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        let look_from = Point3::from(self.look_from);
+        let look_at = Point3::from(self.look_at);
+        Camera::new(
+            look_from,
+            look_at,
+            Vec3::from(self.vup),
+            self.v_fov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist
+                .unwrap_or_else(|| (look_from - look_at).length()),
+        )
+    }
+}
+
+impl CameraDescription {
+    pub fn from_camera(camera: &Camera) -> Self {
+        CameraDescription {
+            look_from: camera.look_from().into(),
+            look_at: camera.look_at().into(),
+            vup: camera.vup().into(),
+            v_fov: camera.v_fov(),
+            aperture: camera.aperture(),
+            focus_dist: Some(camera.focus_dist()),
+        }
+    }
+}
+
+/// Collects the descriptions of a world's objects, naming each distinct
+/// material once. See [`Hittable::describe`].
+#[derive(Default)]
+pub struct SceneExporter {
+    materials: BTreeMap<String, MaterialDescription>,
+    names: HashMap<*const (), String>,
+    objects: Vec<ObjectDescription>,
+}
+
+impl SceneExporter {
+    /// Name of `material` in the exported scene, or `None` if it can't be
+    /// described.
+    pub fn material(&mut self, material: &Arc<dyn Material>) -> Option<String> {
+        let key = Arc::as_ptr(material) as *const ();
+        if let Some(name) = self.names.get(&key) {
+            return Some(name.clone());
+        }
+        let description = material.describe()?;
+        let name = format!("material_{}", self.names.len());
+        self.materials.insert(name.clone(), description);
+        self.names.insert(key, name.clone());
+        Some(name)
+    }
+
+    pub fn add(&mut self, object: ObjectDescription) {
+        self.objects.push(object);
+    }
+}
+
+impl SceneDescription {
+    /// Describes a scene built in code. The aspect ratio is taken from the
+    /// camera so that reloading gives the same camera.
+    pub fn from_scene(
+        world: &dyn Hittable,
+        camera: &Camera,
+        background: &dyn Environment,
+        settings: RenderSettings,
+    ) -> Result<Self, SceneError> {
+        let mut exporter = SceneExporter::default();
+        if !world.describe(&mut exporter) {
+            return Err(SceneError::Export(String::from(
+                "the world has an object or material with no scene description",
+            )));
+        }
+        let background = background.describe().ok_or_else(|| {
+            SceneError::Export(String::from("the background has no scene description"))
+        })?;
+        Ok(SceneDescription {
+            render: RenderSettings {
+                aspect_ratio: camera.aspect_ratio(),
+                ..settings
+            },
+            camera: CameraDescription::from_camera(camera),
+            background,
+            materials: exporter.materials,
+            objects: exporter.objects,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("scene descriptions are valid JSON")
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json()).map_err(|source| SceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Checks the values and builds the scene. `path` is the scene file,
+    /// used in errors and to resolve mesh paths.
+    pub fn build(&self, path: &Path) -> Result<Scene, SceneError> {
+        let invalid = |key: String, message: String| SceneError::Invalid {
+            path: path.to_path_buf(),
+            key,
+            message,
+        };
+
+        let render = &self.render;
+        render
+            .validate()
+            .map_err(|e| invalid(e.key.into(), e.message.into()))?;
+
+        let camera = &self.camera;
+        for (key, point) in [
+            ("camera.look_from", camera.look_from),
+            ("camera.look_at", camera.look_at),
+            ("camera.vup", camera.vup),
+        ]
+        .iter()
+        {
+            if !point.iter().all(|c| c.is_finite()) {
+                return Err(invalid((*key).into(), "must be finite".into()));
+            }
+        }
+        if camera.look_from == camera.look_at {
+            return Err(invalid(
+                "camera.look_at".into(),
+                "must differ from look_from".into(),
+            ));
+        }
+        // the camera's horizontal axis is vup crossed with the view direction
+        let w = (Vec3::from(camera.look_from) - Vec3::from(camera.look_at)).unit();
+        let sine = Vec3::from(camera.vup).unit().cross(w).length();
+        if !(sine.is_finite() && sine > 1e-6) {
+            return Err(invalid(
+                "camera.vup".into(),
+                "must not be zero or parallel to the view direction".into(),
+            ));
+        }
+        if !(camera.v_fov > 0. && camera.v_fov < 180.) {
+            return Err(invalid(
+                "camera.v_fov".into(),
+                "must be between 0 and 180 degrees".into(),
+            ));
+        }
+
+        let (colors, transform) = match &self.background {
+            BackgroundDescription::Solid(color) => (vec![*color], None),
+            BackgroundDescription::Gradient {
+                bottom,
+                top,
+                rotation,
+                intensity,
+            } => (vec![*bottom, *top], Some((rotation, *intensity))),
+            BackgroundDescription::Equirect {
+                rotation,
+                intensity,
+                ..
+            }
+            | BackgroundDescription::CubeMap {
+                rotation,
+                intensity,
+                ..
+            } => (vec![], Some((rotation, *intensity))),
+            BackgroundDescription::PhysicalSky { intensity, .. } => {
+                (vec![], Some((&[0.; 3], *intensity)))
+            }
+            _ => (vec![], None),
+        };
+        if colors
+            .iter()
+            .flatten()
+            .any(|c| !(c.is_finite() && *c >= 0.))
+        {
+            return Err(invalid(
+                "background".into(),
+                "colours must not be negative".into(),
+            ));
+        }
+        if let Some((rotation, intensity)) = transform {
+            if !rotation.iter().all(|r| r.is_finite()) {
+                return Err(invalid(
+                    "background.rotation".into(),
+                    "must be finite".into(),
+                ));
+            }
+            if !(intensity.is_finite() && intensity >= 0.) {
+                return Err(invalid(
+                    "background.intensity".into(),
+                    "must not be negative".into(),
+                ));
+            }
+        }
+        if let BackgroundDescription::PhysicalSky { sun, turbidity, .. } = &self.background {
+            let sun_error = match *sun {
+                SunDescription::Angles { elevation, azimuth } => {
+                    if !(-90. ..=90.).contains(&elevation) {
+                        Some("elevation must be between -90 and 90 degrees")
+                    } else if !azimuth.is_finite() {
+                        Some("azimuth must be finite")
+                    } else {
+                        None
+                    }
+                }
+                SunDescription::Location {
+                    latitude,
+                    longitude,
+                    year,
+                    month,
+                    day,
+                    hour,
+                    utc_offset,
+                } => {
+                    if !(-90. ..=90.).contains(&latitude) {
+                        Some("latitude must be between -90 and 90 degrees")
+                    } else if !(-180. ..=180.).contains(&longitude) {
+                        Some("longitude must be between -180 and 180 degrees")
+                    } else if !(1..=12).contains(&month) {
+                        Some("month must be between 1 and 12")
+                    } else if !(1..=days_in_month(year, month)).contains(&day) {
+                        Some("day is not in the month")
+                    } else if !(hour.is_finite() && utc_offset.is_finite()) {
+                        Some("hour and utc_offset must be finite")
+                    } else {
+                        None
+                    }
+                }
+            };
+            if let Some(message) = sun_error {
+                return Err(invalid("background.sun".into(), message.into()));
+            }
+            if !(2. ..=10.).contains(turbidity) {
+                return Err(invalid(
+                    "background.turbidity".into(),
+                    "must be between 2 and 10".into(),
+                ));
+            }
+        }
+
+        let mut materials = HashMap::new();
+        for (name, material) in self.materials.iter() {
+            let key = || format!("materials.{}", name);
+            match *material {
+                MaterialDescription::Metal { fuzz, .. } if !(0. ..=1.).contains(&fuzz) => {
+                    return Err(invalid(key(), "fuzz must be between 0 and 1".into()))
+                }
+                MaterialDescription::Dielectric { refraction_index }
+                    if !(refraction_index.is_finite() && refraction_index > 0.) =>
+                {
+                    return Err(invalid(key(), "refraction_index must be positive".into()))
+                }
+                MaterialDescription::DiffuseLight { emit }
+                    if !emit.iter().all(|c| c.is_finite() && *c >= 0.) =>
+                {
+                    return Err(invalid(key(), "emit must not be negative".into()))
+                }
+                _ => {}
+            }
+            materials.insert(name.as_str(), material.build());
+        }
+        let material = |i: usize, name: &str| {
+            materials.get(name).cloned().ok_or_else(|| {
+                invalid(
+                    format!("objects[{}].material", i),
+                    format!("unknown material '{}'", name),
+                )
+            })
+        };
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mesh_error =
+            |i: usize, e: &dyn error::Error| invalid(format!("objects[{}].path", i), e.to_string());
+        let no_faces =
+            |i: usize| invalid(format!("objects[{}].path", i), "mesh has no faces".into());
+
+        let mut world = HittableList::default();
+        for (i, object) in self.objects.iter().enumerate() {
+            match object {
+                ObjectDescription::Sphere {
+                    center,
+                    radius,
+                    material: name,
+                } => {
+                    // a negative radius is allowed, it turns the sphere inside out
+                    if !(radius.is_finite() && *radius != 0.) {
+                        return Err(invalid(
+                            format!("objects[{}].radius", i),
+                            "must be finite and not zero".into(),
+                        ));
+                    }
+                    world.add(Arc::new(Sphere {
+                        center: Point3::from(*center),
+                        radius: *radius,
+                        material: material(i, name)?,
+                    }));
+                }
+                ObjectDescription::Triangle {
+                    vertices: [v0, v1, v2],
+                    normals,
+                    material: name,
+                } => {
+                    let mut triangle = Triangle::new(
+                        Point3::from(*v0),
+                        Point3::from(*v1),
+                        Point3::from(*v2),
+                        material(i, name)?,
+                    );
+                    if let Some([n0, n1, n2]) = normals {
+                        triangle = triangle.with_normals(
+                            Vec3::from(*n0),
+                            Vec3::from(*n1),
+                            Vec3::from(*n2),
+                        );
+                    }
+                    world.add(Arc::new(triangle));
+                }
+                ObjectDescription::Mesh {
+                    positions,
+                    indices,
+                    normals,
+                    uvs,
+                    colors,
+                    material: name,
+                } => {
+                    let count = positions.len();
+                    let key = |field: &str| format!("objects[{}].{}", i, field);
+                    if indices.is_empty() {
+                        return Err(invalid(key("indices"), "mesh has no faces".into()));
+                    }
+                    if indices.iter().flatten().any(|&v| v as usize >= count) {
+                        return Err(invalid(key("indices"), "index out of range".into()));
+                    }
+                    for (field, len) in [
+                        ("normals", normals.len()),
+                        ("uvs", uvs.len()),
+                        ("colors", colors.len()),
+                    ] {
+                        if len != 0 && len != count {
+                            return Err(invalid(key(field), "needs one entry per position".into()));
+                        }
+                    }
+                    let to_vecs = |vs: &[[f64; 3]]| vs.iter().map(|&v| Vec3::from(v)).collect();
+                    let mut mesh =
+                        TriangleMesh::new(to_vecs(positions), indices.clone(), material(i, name)?);
+                    if !normals.is_empty() {
+                        mesh = mesh.with_normals(to_vecs(normals));
+                    }
+                    if !uvs.is_empty() {
+                        mesh = mesh.with_uvs(uvs.clone());
+                    }
+                    if !colors.is_empty() {
+                        mesh = mesh.with_colors(to_vecs(colors));
+                    }
+                    world.add(Arc::new(mesh));
+                }
+                ObjectDescription::Obj {
+                    path: file,
+                    material: name,
+                } => {
+                    let model = load_obj(dir.join(file)).map_err(|e| mesh_error(i, &e))?;
+                    if model.meshes.iter().all(|mesh| mesh.mesh.face_count() == 0) {
+                        return Err(no_faces(i));
+                    }
+                    let material = name.as_ref().map(|name| material(i, name)).transpose()?;
+                    for mut mesh in model.meshes {
+                        if let Some(material) = &material {
+                            mesh.mesh.material = material.clone();
+                        }
+                        world.add(Arc::new(mesh.mesh));
+                    }
+                }
+                ObjectDescription::Ply {
+                    path: file,
+                    material: name,
+                } => {
+                    let mesh = load_ply(dir.join(file), material(i, name)?)
+                        .map_err(|e| mesh_error(i, &e))?;
+                    if mesh.face_count() == 0 {
+                        return Err(no_faces(i));
+                    }
+                    world.add(Arc::new(mesh));
+                }
+                ObjectDescription::Stl {
+                    path: file,
+                    material: name,
+                    weld_tolerance,
+                    crease_angle,
+                } => {
+                    let defaults = StlOptions::default();
+                    let options = StlOptions {
+                        weld_tolerance: weld_tolerance.unwrap_or(defaults.weld_tolerance),
+                        crease_angle: crease_angle.unwrap_or(defaults.crease_angle),
+                    };
+                    let mesh = load_stl(dir.join(file), material(i, name)?, options)
+                        .map_err(|e| mesh_error(i, &e))?;
+                    if mesh.face_count() == 0 {
+                        return Err(no_faces(i));
+                    }
+                    world.add(Arc::new(mesh));
+                }
+            }
+        }
+
+        Ok(Scene {
+            world,
+            camera: self.camera.build(render.aspect_ratio),
+            background: self
+                .background
+                .build(dir)
+                .map_err(|e| invalid("background".into(), e.to_string()))?,
+            settings: *render,
+        })
+    }
+}
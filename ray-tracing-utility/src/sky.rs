@@ -0,0 +1,485 @@
+use crate::*;
+use std::f64::consts::PI;
+
+/// Angular radius of the sun seen from the earth, in degrees.
+pub const SUN_ANGULAR_RADIUS: f64 = 0.2667;
+
+/// Luminance of the sun above the atmosphere, in kcd/m²: its illuminance
+/// of 133.3 klux spread over the solid angle of its disk.
+const SUN_LUMINANCE: f64 = 1.96e6;
+
+/// Where the sun is in the sky, in degrees. Azimuth is measured clockwise
+/// from north, so east is 90°. North is along -z and east along +x.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SunPosition {
+    pub elevation: f64,
+    pub azimuth: f64,
+}
+
+impl SunPosition {
+    /// Position of the sun seen from `latitude` and `longitude`, in degrees
+    /// north and east, at `hour` (UTC) of the given day. Uses NOAA's solar
+    /// position equations, good to a few hundredths of a degree; refraction
+    /// by the atmosphere is left out.
+    pub fn at(latitude: f64, longitude: f64, year: i32, month: u32, day: u32, hour: f64) -> Self {
+        let jd = julian_day(year, month, day) + hour / 24.;
+        let t = (jd - 2451545.) / 36525.;
+
+        // the sun's ecliptic longitude, and the obliquity of the ecliptic
+        let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.);
+        let mean_anomaly = 357.52911 + t * (35999.05029 - t * 0.0001537);
+        let eccentricity = 0.016708634 - t * (0.000042037 + t * 0.0000001267);
+        let m = mean_anomaly.to_radians();
+        let center = m.sin() * (1.914602 - t * (0.004817 + t * 0.000014))
+            + (2. * m).sin() * (0.019993 - t * 0.000101)
+            + (3. * m).sin() * 0.000289;
+        let omega = (125.04 - 1934.136 * t).to_radians();
+        let longitude_sun =
+            (mean_longitude + center - 0.00569 - 0.00478 * omega.sin()).to_radians();
+        let obliquity =
+            23. + (26. + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.) / 60.;
+        let obliquity = (obliquity + 0.00256 * omega.cos()).to_radians();
+        let declination = (obliquity.sin() * longitude_sun.sin()).asin();
+
+        // equation of time, in minutes
+        let y = (obliquity / 2.).tan().powi(2);
+        let l0 = mean_longitude.to_radians();
+        let equation_of_time = 4.
+            * (y * (2. * l0).sin() - 2. * eccentricity * m.sin()
+                + 4. * eccentricity * y * m.sin() * (2. * l0).cos()
+                - 0.5 * y * y * (4. * l0).sin()
+                - 1.25 * eccentricity * eccentricity * (2. * m).sin())
+            .to_degrees();
+        let solar_minutes = hour * 60. + equation_of_time + 4. * longitude;
+        let hour_angle = (solar_minutes / 4. - 180.).to_radians();
+
+        let latitude = latitude.to_radians();
+        let elevation = (latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos())
+        .clamp(-1., 1.)
+        .asin();
+        let azimuth = hour_angle
+            .sin()
+            .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos());
+        SunPosition {
+            elevation: elevation.to_degrees(),
+            azimuth: (azimuth.to_degrees() + 180.).rem_euclid(360.),
+        }
+    }
+
+    /// Unit vector towards the sun.
+    pub fn direction(&self) -> Vec3 {
+        let (elevation, azimuth) = (self.elevation.to_radians(), self.azimuth.to_radians());
+        Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+    }
+}
+
+/// Julian day number at midnight UTC starting the given Gregorian date.
+fn julian_day(year: i32, month: u32, day: u32) -> f64 {
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 12)
+    } else {
+        (year, month)
+    };
+    let a = (year as f64 / 100.).floor();
+    let b = 2. - a + (a / 4.).floor();
+    (365.25 * (year as f64 + 4716.)).floor()
+        + (30.6001 * (month as f64 + 1.)).floor()
+        + day as f64
+        + b
+        - 1524.5
+}
+
+/// The sun's disk: constant radiance within a small cone of directions.
+#[derive(Copy, Clone)]
+pub struct SunLight {
+    /// Unit vector towards the centre of the disk.
+    pub direction: Vec3,
+    pub radiance: Color,
+    // cosine of the disk's angular radius
+    cos_radius: f64,
+}
+
+impl SunLight {
+    pub fn new(direction: Vec3, angular_radius: f64, radiance: Color) -> Self {
+        SunLight {
+            direction: direction.unit(),
+            radiance,
+            cos_radius: angular_radius.to_radians().cos(),
+        }
+    }
+
+    /// The sun as seen through `turbidity` (see [`PreethamSky`]), dimmed
+    /// and reddened by the air its light crosses.
+    pub fn through_atmosphere(sun: SunPosition, turbidity: f64) -> Self {
+        SunLight::new(
+            sun.direction(),
+            SUN_ANGULAR_RADIUS,
+            sun_transmittance(sun.elevation, turbidity) * SUN_LUMINANCE,
+        )
+    }
+
+    /// Radiance arriving from `direction`, a unit vector: the sun's inside
+    /// the disk and nothing outside it.
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        if direction.dot(self.direction) >= self.cos_radius {
+            self.radiance
+        } else {
+            Color::default()
+        }
+    }
+
+    /// Solid angle of the disk.
+    pub fn solid_angle(&self) -> f64 {
+        2. * PI * (1. - self.cos_radius)
+    }
+
+    /// Uniformly distributed direction within the disk, with its density.
+    fn sample_direction(&self, u: [f64; 2]) -> (Vec3, f64) {
+        let cos_theta = 1. - u[0] * (1. - self.cos_radius);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * u[1];
+        let (s, t) = basis(self.direction);
+        let direction =
+            s * (sin_theta * phi.cos()) + t * (sin_theta * phi.sin()) + self.direction * cos_theta;
+        (direction, 1. / self.solid_angle())
+    }
+
+    fn direction_pdf(&self, direction: Vec3) -> f64 {
+        if direction.dot(self.direction) >= self.cos_radius {
+            1. / self.solid_angle()
+        } else {
+            0.
+        }
+    }
+}
+
+impl Light for SunLight {
+    fn sample(&self, _point: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let (direction, pdf) = self.sample_direction(sampler.get_2d());
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance,
+            pdf,
+        })
+    }
+
+    fn pdf(&self, _point: Point3, direction: Vec3) -> f64 {
+        self.direction_pdf(direction)
+    }
+}
+
+/// Two unit vectors that make an orthonormal basis with `n`, after Duff et
+/// al., "Building an Orthonormal Basis, Revisited".
+fn basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1f64.copysign(n.z);
+    let a = -1. / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1. + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+/// Share of the sun's light at 680, 550 and 440 nm that gets through the
+/// air to the ground, from Rayleigh and aerosol scattering as in appendix
+/// A.2 of Preetham et al.
+fn sun_transmittance(elevation: f64, turbidity: f64) -> Color {
+    if elevation <= 0. {
+        return Color::default();
+    }
+    let zenith = 90. - elevation;
+    let optical_mass = 1. / (zenith.to_radians().cos() + 0.15 * (93.885 - zenith).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |wavelength: f64| {
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let aerosol = beta * wavelength.powf(-1.3);
+        (-(rayleigh + aerosol) * optical_mass).exp()
+    };
+    Color::new(
+        transmittance(0.68),
+        transmittance(0.55),
+        transmittance(0.44),
+    )
+}
+
+/// Preetham, Shirley and Smits' analytic daylight sky, "A Practical
+/// Analytic Model for Daylight" (1999), with the sun's disk in it.
+/// Radiance is in kcd/m² times `intensity`, so scenes lit by it want an
+/// exposure of around -5. Turbidity says how hazy the air is: 2 is very
+/// clear, 3 a clear day, 6 a warm, moist day and 10 haze; the model was fit
+/// from 2 to 10.
+///
+/// The model only covers the sun above the horizon. Below it the sky is
+/// that of a sun on the horizon, without the disk, and below the horizon
+/// every direction sees the sky's colour at the horizon.
+#[derive(Clone)]
+pub struct PreethamSky {
+    sun_position: SunPosition,
+    turbidity: f64,
+    intensity: f64,
+    sun: Option<SunLight>,
+    // unit vector towards the sun, held at or above the horizon
+    sun_direction: Vec3,
+    // Perez coefficients and zenith values of Y, x and y
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    // chance of sampling the sun rather than the whole sphere
+    sun_probability: f64,
+}
+
+impl PreethamSky {
+    pub fn new(sun_position: SunPosition, turbidity: f64, intensity: f64) -> Self {
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let elevation = sun_position.elevation.max(0.);
+        let theta_sun = (90. - elevation).to_radians();
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let angles = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.];
+            let row = |r: [f64; 4]| (0..4).map(|i| r[i] * angles[i]).sum::<f64>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let sun_direction = SunPosition {
+            elevation,
+            ..sun_position
+        }
+        .direction();
+        let sun = if sun_position.elevation > 0. {
+            let mut sun = SunLight::through_atmosphere(sun_position, turbidity);
+            sun.radiance = sun.radiance * intensity;
+            Some(sun)
+        } else {
+            None
+        };
+        let mut sky = PreethamSky {
+            sun_position,
+            turbidity,
+            intensity,
+            sun,
+            sun_direction,
+            perez,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            sun_probability: 0.,
+        };
+        sky.sun_probability = sky.balance_sun();
+        sky
+    }
+
+    pub fn sun_position(&self) -> SunPosition {
+        self.sun_position
+    }
+
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+
+    /// The sun's disk as a light of its own; none while it is below the
+    /// horizon.
+    pub fn sun(&self) -> Option<&SunLight> {
+        self.sun.as_ref()
+    }
+
+    /// Radiance of the sky alone, without the sun's disk.
+    pub fn sky_radiance(&self, direction: Vec3) -> Color {
+        let unit = direction.unit();
+        // a cosine near 0 would blow up the horizon term
+        let cos_theta = unit.y.max(1e-3);
+        let cos_gamma = unit.dot(self.sun_direction).clamp(-1., 1.);
+        let cos_theta_sun = self.sun_direction.y;
+        let value = |i: usize| {
+            let perez = |cos_theta: f64, cos_gamma: f64| {
+                let [a, b, c, d, e] = self.perez[i];
+                (1. + a * (b / cos_theta).exp())
+                    * (1. + c * (d * cos_gamma.acos()).exp() + e * cos_gamma * cos_gamma)
+            };
+            self.zenith[i] * perez(cos_theta, cos_gamma) / perez(1., cos_theta_sun)
+        };
+        xyy_to_rgb(value(1), value(2), value(0)) * self.intensity
+    }
+
+    /// Picks the sun about as often as its share of the light calls for,
+    /// but never so often the rest of the sky goes without samples.
+    fn balance_sun(&self) -> f64 {
+        let sun = match &self.sun {
+            Some(sun) => luminance(sun.radiance) * sun.solid_angle(),
+            None => return 0.,
+        };
+        // the rest of the sky, from a coarse grid of directions
+        let (rows, columns) = (16, 32);
+        let mut sky = 0.;
+        for row in 0..rows {
+            for column in 0..columns {
+                let u = [
+                    (row as f64 + 0.5) / rows as f64,
+                    (column as f64 + 0.5) / columns as f64,
+                ];
+                sky += luminance(self.sky_radiance(sample_unit_sphere(u)));
+            }
+        }
+        let sky = sky / (rows * columns) as f64 * 4. * PI;
+        if sun + sky <= 0. {
+            return 0.;
+        }
+        (sun / (sun + sky)).clamp(0.1, 0.9)
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let sky = self.sky_radiance(direction);
+        match &self.sun {
+            Some(sun) => sky + sun.radiance(direction.unit()),
+            None => sky,
+        }
+    }
+
+    fn sample_direction(&self, u: [f64; 2]) -> (Vec3, f64) {
+        let p = self.sun_probability;
+        let direction = match &self.sun {
+            Some(sun) if u[0] < p => sun.sample_direction([u[0] / p, u[1]]).0,
+            _ => sample_unit_sphere([(u[0] - p) / (1. - p), u[1]]),
+        };
+        (direction, self.pdf(direction))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let p = self.sun_probability;
+        let sun = self.sun.map_or(0., |sun| sun.direction_pdf(direction));
+        p * sun + (1. - p) / (4. * PI)
+    }
+
+    fn describe(&self) -> Option<BackgroundDescription> {
+        Some(BackgroundDescription::PhysicalSky {
+            sun: SunDescription::Angles {
+                elevation: self.sun_position.elevation,
+                azimuth: self.sun_position.azimuth,
+            },
+            turbidity: self.turbidity,
+            intensity: self.intensity,
+        })
+    }
+}
+
+/// CIE xyY to linear sRGB, with negative channels clipped.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0. {
+        return Color::default();
+    }
+    let (cx, cy, cz) = (x / y * luminance, luminance, (1. - x - y) / y * luminance);
+    Color::new(
+        (3.2406 * cx - 1.5372 * cy - 0.4986 * cz).max(0.),
+        (-0.9689 * cx + 1.8758 * cy + 0.0415 * cz).max(0.),
+        (0.0557 * cx - 0.2040 * cy + 1.0570 * cz).max(0.),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sky() -> PreethamSky {
+        let sun = SunPosition {
+            elevation: 35.,
+            azimuth: 120.,
+        };
+        PreethamSky::new(sun, 3., 1.)
+    }
+
+    fn grid(n: usize) -> impl Iterator<Item = [f64; 2]> {
+        (0..n * n).map(move |i| {
+            [
+                ((i % n) as f64 + 0.5) / n as f64,
+                ((i / n) as f64 + 0.5) / n as f64,
+            ]
+        })
+    }
+
+    #[test]
+    fn sky_sample_density_matches_pdf() {
+        let sky = sky();
+        assert!(sky.sun_probability > 0.);
+        for u in grid(101) {
+            let (direction, pdf) = sky.sample_direction(u);
+            assert!(pdf > 0.);
+            assert_eq!(pdf, sky.pdf(direction));
+        }
+    }
+
+    #[test]
+    fn sky_density_covers_the_sphere() {
+        // the mean of 1 / pdf over the samples estimates the solid angle the
+        // density is spread over
+        let sky = sky();
+        let n = 400;
+        let solid_angle =
+            grid(n).map(|u| 1. / sky.sample_direction(u).1).sum::<f64>() / (n * n) as f64;
+        assert!(
+            (solid_angle / (4. * PI) - 1.).abs() < 1e-2,
+            "{}",
+            solid_angle
+        );
+    }
+
+    #[test]
+    fn sun_sample_density_matches_pdf() {
+        let sun = sky().sun().copied().unwrap();
+        let point = Point3::new(1., 2., 3.);
+        let mut sampler = IndependentSampler::new(7);
+        for _ in 0..1000 {
+            let sample = sun.sample(point, &mut sampler).unwrap();
+            assert!(sample.direction.dot(sun.direction) >= sun.cos_radius - 1e-12);
+            let pdf = sun.pdf(point, sample.direction);
+            assert!(
+                (sample.pdf - pdf).abs() <= 1e-9 * pdf,
+                "{} != {}",
+                sample.pdf,
+                pdf
+            );
+        }
+    }
+}
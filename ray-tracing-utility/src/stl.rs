@@ -0,0 +1,301 @@
+use crate::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::{error, fmt, fs, io};
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(io::Error),
+    Ascii { line: usize, message: String },
+    Binary(String),
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StlError::Io(e) => write!(f, "stl: {}", e),
+            StlError::Ascii { line, message } => write!(f, "stl, line {}: {}", line, message),
+            StlError::Binary(message) => write!(f, "binary stl: {}", message),
+        }
+    }
+}
+
+impl error::Error for StlError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StlError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StlError {
+    fn from(e: io::Error) -> Self {
+        StlError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct StlOptions {
+    /// Vertices closer than this are merged into one.
+    pub weld_tolerance: f64,
+    /// Faces meeting at a sharper angle than this, in degrees, keep a hard
+    /// edge between them. Zero gives a faceted mesh.
+    pub crease_angle: f64,
+}
+
+impl Default for StlOptions {
+    fn default() -> Self {
+        StlOptions {
+            weld_tolerance: 1e-5,
+            crease_angle: 30.,
+        }
+    }
+}
+
+pub fn load_stl<P: AsRef<Path>>(
+    path: P,
+    material: Arc<dyn Material>,
+    options: StlOptions,
+) -> Result<TriangleMesh, StlError> {
+    let bytes = fs::read(path)?;
+    parse_stl(&bytes, material, options)
+}
+
+/// Reads an ASCII or binary STL file. STL stores every triangle with its
+/// own copy of its corners, so vertices are welded back together and given
+/// smooth normals wherever the surface bends less than the crease angle.
+pub fn parse_stl(
+    bytes: &[u8],
+    material: Arc<dyn Material>,
+    options: StlOptions,
+) -> Result<TriangleMesh, StlError> {
+    let triangles = if is_binary(bytes) {
+        parse_binary(bytes)?
+    } else {
+        parse_ascii(bytes)?
+    };
+    Ok(build_mesh(&triangles, material, options))
+}
+
+/// Binary files can start with "solid" too, so the size is what decides.
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if bytes.len() == 84 + 50 * count {
+            return true;
+        }
+    }
+    !bytes.trim_ascii_start().starts_with(b"solid")
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<[Point3; 3]>, StlError> {
+    if bytes.len() < 84 {
+        return Err(StlError::Binary(String::from(
+            "file is shorter than the header",
+        )));
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let body = &bytes[84..];
+    if body.len() < 50 * count {
+        return Err(StlError::Binary(format!(
+            "header says {} triangles but there are only {} bytes of data",
+            count,
+            body.len()
+        )));
+    }
+    let f = |b: &[u8], i: usize| f32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]) as f64;
+    Ok(body
+        .chunks_exact(50)
+        .take(count)
+        .map(|record| {
+            // 12 bytes of facet normal, three vertices, then 2 attribute bytes
+            let v = |k: usize| {
+                let i = 12 + 12 * k;
+                Point3::new(f(record, i), f(record, i + 4), f(record, i + 8))
+            };
+            [v(0), v(1), v(2)]
+        })
+        .collect())
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<[Point3; 3]>, StlError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| StlError::Ascii {
+        line: 1,
+        message: String::from("not a text file"),
+    })?;
+    let mut triangles = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+    let mut line = 0;
+    for (i, raw) in text.lines().enumerate() {
+        line = i + 1;
+        let error = |message: String| StlError::Ascii { line, message };
+        let words = raw.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] | ["solid", ..] | ["endsolid", ..] | ["outer", "loop"] => {}
+            ["facet", "normal", _, _, _] => corners.clear(),
+            ["vertex", x, y, z] => {
+                let number = |w: &str| {
+                    w.parse::<f64>()
+                        .map_err(|_| error(format!("invalid number '{}'", w)))
+                };
+                if corners.len() == 3 {
+                    return Err(error(String::from("facet has more than 3 vertices")));
+                }
+                corners.push(Point3::new(number(x)?, number(y)?, number(z)?));
+            }
+            ["endloop"] => {
+                if corners.len() != 3 {
+                    return Err(error(format!("facet has {} vertices", corners.len())));
+                }
+            }
+            ["endfacet"] => {
+                if corners.len() != 3 {
+                    return Err(error(String::from("endfacet without a vertex loop")));
+                }
+                triangles.push([corners[0], corners[1], corners[2]]);
+                corners.clear();
+            }
+            _ => return Err(error(format!("unexpected '{}'", raw.trim()))),
+        }
+    }
+    if !corners.is_empty() {
+        return Err(StlError::Ascii {
+            line,
+            message: String::from("unterminated facet"),
+        });
+    }
+    Ok(triangles)
+}
+
+/// Merges vertices that are within `tolerance` of each other, bucketing
+/// them in a grid of that cell size so only neighbouring cells are searched.
+fn weld(triangles: &[[Point3; 3]], tolerance: f64) -> (Vec<Point3>, Vec<[u32; 3]>) {
+    let cell = |p: Point3| {
+        if tolerance > 0. {
+            (
+                (p.x / tolerance).floor() as i64,
+                (p.y / tolerance).floor() as i64,
+                (p.z / tolerance).floor() as i64,
+            )
+        } else {
+            (
+                p.x.to_bits() as i64,
+                p.y.to_bits() as i64,
+                p.z.to_bits() as i64,
+            )
+        }
+    };
+    let mut grid: HashMap<(i64, i64, i64), Vec<u32>> = HashMap::new();
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut indices = Vec::with_capacity(triangles.len());
+
+    for triangle in triangles.iter() {
+        let mut face = [0u32; 3];
+        for (slot, &p) in face.iter_mut().zip(triangle.iter()) {
+            let (cx, cy, cz) = cell(p);
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidates) = grid.get(&(
+                            cx.wrapping_add(dx),
+                            cy.wrapping_add(dy),
+                            cz.wrapping_add(dz),
+                        )) {
+                            for &i in candidates.iter() {
+                                if (positions[i as usize] - p).length_squared()
+                                    <= tolerance * tolerance
+                                {
+                                    found = Some(i);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            *slot = match found {
+                Some(i) => i,
+                None => {
+                    let i = positions.len() as u32;
+                    positions.push(p);
+                    grid.entry((cx, cy, cz)).or_default().push(i);
+                    i
+                }
+            };
+        }
+        // welding can collapse thin triangles
+        let [a, b, c] = face.map(|i| positions[i as usize]);
+        if (b - a).cross(c - a).length_squared() > 0. {
+            indices.push(face);
+        }
+    }
+    (positions, indices)
+}
+
+fn build_mesh(
+    triangles: &[[Point3; 3]],
+    material: Arc<dyn Material>,
+    options: StlOptions,
+) -> TriangleMesh {
+    let (positions, indices) = weld(triangles, options.weld_tolerance);
+    if options.crease_angle <= 0. {
+        return TriangleMesh::new(positions, indices, material);
+    }
+
+    // unnormalized face normals, so larger faces weigh more
+    let face_normals = indices
+        .iter()
+        .map(|&[a, b, c]| {
+            let (a, b, c) = (
+                positions[a as usize],
+                positions[b as usize],
+                positions[c as usize],
+            );
+            (b - a).cross(c - a)
+        })
+        .collect::<Vec<_>>();
+    let mut incident: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+    for (f, face) in indices.iter().enumerate() {
+        for &v in face.iter() {
+            incident[v as usize].push(f);
+        }
+    }
+
+    // A corner's normal averages the faces around its vertex that are within
+    // the crease angle of its own face. Corners that end up with the same
+    // normal share a vertex, so only vertices on creases get split.
+    let cos_crease = options.crease_angle.to_radians().cos();
+    let mut split: HashMap<(u32, [u64; 3]), u32> = HashMap::new();
+    let mut mesh_positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut mesh_indices = Vec::with_capacity(indices.len());
+    for (f, face) in indices.iter().enumerate() {
+        let n_f = face_normals[f].unit();
+        let mut corners = [0u32; 3];
+        for (slot, &v) in corners.iter_mut().zip(face.iter()) {
+            let mut n = Vec3::default();
+            for &g in incident[v as usize].iter() {
+                let n_g = face_normals[g];
+                if g == f || n_f.dot(n_g.unit()) >= cos_crease {
+                    n += n_g;
+                }
+            }
+            // with a crease angle of 90 degrees or more, back to back faces
+            // can cancel out and leave nothing to normalise
+            if n.dot(n_f) <= 0. {
+                n = face_normals[f];
+            }
+            let key = (v, [n.x.to_bits(), n.y.to_bits(), n.z.to_bits()]);
+            *slot = *split.entry(key).or_insert_with(|| {
+                mesh_positions.push(positions[v as usize]);
+                normals.push(n.unit());
+                (mesh_positions.len() - 1) as u32
+            });
+        }
+        mesh_indices.push(corners);
+    }
+    TriangleMesh::new(mesh_positions, mesh_indices, material).with_normals(normals)
+}
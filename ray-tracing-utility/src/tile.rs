@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+/// A rectangle of pixels that is rendered as one unit of work, counted from
+/// the top left of the image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    pub fn area(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Coordinates of the tile's pixels, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let Tile {
+            x,
+            y,
+            width,
+            height,
+        } = *self;
+        (y..y + height).flat_map(move |y| (x..x + width).map(move |x| (x, y)))
+    }
+}
+
+/// Order in which the tiles of an image are started.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    Scanline,
+    /// Outwards from the centre of the image, where the subject usually is.
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, so tiles that are rendered at the same time are
+    /// close together and share more of the scene in the caches.
+    Hilbert,
+}
+
+/// Splits a `width` x `height` image into tiles of `size` x `size` pixels,
+/// smaller along the right and bottom edges, in the given order.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+    let tile = |(column, row): (usize, usize)| {
+        let (x, y) = (column * size, row * size);
+        Tile {
+            x,
+            y,
+            width: size.min(width - x),
+            height: size.min(height - y),
+        }
+    };
+    match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(tile)
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows).map(tile).collect(),
+        TileOrder::Hilbert => hilbert(columns, rows).map(tile).collect(),
+    }
+}
+
+/// Cells of a `columns` x `rows` grid in a square spiral that starts at
+/// the centre cell and turns clockwise.
+fn spiral(columns: usize, rows: usize) -> impl Iterator<Item = (usize, usize)> {
+    const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let total = columns * rows;
+    let (mut x, mut y) = (((columns as isize) - 1) / 2, ((rows as isize) - 1) / 2);
+    // the spiral runs 1, 1, 2, 2, 3, 3, ... cells before each turn
+    let (mut direction, mut leg, mut step) = (0, 1, 0);
+    let mut found = 0;
+    std::iter::from_fn(move || {
+        while found < total {
+            let cell = (x, y);
+            let (dx, dy) = DIRECTIONS[direction];
+            x += dx;
+            y += dy;
+            step += 1;
+            if step == leg {
+                step = 0;
+                direction = (direction + 1) % 4;
+                if direction % 2 == 0 {
+                    leg += 1;
+                }
+            }
+            if (0..columns as isize).contains(&cell.0) && (0..rows as isize).contains(&cell.1) {
+                found += 1;
+                return Some((cell.0 as usize, cell.1 as usize));
+            }
+        }
+        None
+    })
+}
+
+/// Cells of a `columns` x `rows` grid along the Hilbert curve of the
+/// smallest power-of-two square that covers it.
+fn hilbert(columns: usize, rows: usize) -> impl Iterator<Item = (usize, usize)> {
+    let n = columns.max(rows).next_power_of_two();
+    (0..n * n)
+        .map(move |d| hilbert_cell(n, d))
+        .filter(move |&(x, y)| x < columns && y < rows)
+}
+
+/// Cell `d` of the Hilbert curve through an `n` x `n` grid.
+fn hilbert_cell(n: usize, mut d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut s = 1;
+    while s < n {
+        let rx = 1 & (d / 2);
+        let ry = 1 & (d ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        d /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
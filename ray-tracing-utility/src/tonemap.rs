@@ -0,0 +1,185 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+
+/// Curve that compresses linear radiance into the displayable `[0, 1]`
+/// range. All of them work on each channel separately.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapOperator {
+    /// Leaves the radiance as it is; anything above 1 is clipped.
+    Clamp,
+    /// `x / (1 + x)`
+    Reinhard,
+    /// Reinhard with a white point: `white` and brighter map to 1.
+    ExtendedReinhard { white: f64 },
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+    /// Stephen Hill's fit of the ACES reference and sRGB output transforms.
+    Aces,
+}
+
+/// Turns the linear radiance of a [`Film`] into display values: exposure,
+/// then white balance, then the tone curve. Encoding the result with the
+/// sRGB transfer function is left to [`ToneMapping::to_rgb8`].
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToneMapping {
+    /// Exposure compensation in stops; every +1 doubles the brightness.
+    pub exposure: f64,
+    /// Colour temperature, in Kelvin, of the light that should come out
+    /// white. 6504 (D65) leaves the colours nearly, but not exactly,
+    /// unchanged, as the daylight curve only approximates D65's white
+    /// point; leave it unset for no adjustment at all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub white_balance: Option<f64>,
+    pub operator: ToneMapOperator,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 0.,
+            white_balance: None,
+            operator: ToneMapOperator::Clamp,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Maps linear radiance to linear display values in `[0, 1]`.
+    pub fn apply(&self, radiance: Color) -> Color {
+        let mut c = radiance * 2f64.powf(self.exposure);
+        if let Some(kelvin) = self.white_balance {
+            c = mul_vector(&white_balance_matrix(kelvin), c);
+        }
+        let c = match self.operator {
+            ToneMapOperator::Clamp => c,
+            ToneMapOperator::Reinhard => map(c, |x| x / (1. + x)),
+            ToneMapOperator::ExtendedReinhard { white } => {
+                map(c, |x| x * (1. + x / (white * white)) / (1. + x))
+            }
+            ToneMapOperator::Hable => {
+                let white_scale = 1. / hable(11.2);
+                map(c, |x| hable(2. * x) * white_scale)
+            }
+            ToneMapOperator::Aces => aces_fitted(c),
+        };
+        map(c, |x| clamp(x, 0., 1.))
+    }
+
+    /// Tone maps and sRGB-encodes radiance to 8 bits.
+    pub fn to_rgb8(&self, radiance: Color) -> [u8; 3] {
+        let c = self.apply(radiance);
+        let quantize = |x: f64| (srgb_encode(x) * 255. + 0.5) as u8;
+        [quantize(c.x), quantize(c.y), quantize(c.z)]
+    }
+}
+
+/// The sRGB transfer function, linear light to encoded value.
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Inverse of [`srgb_encode`].
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn map(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    // NaNs from a broken sample would otherwise survive every curve
+    let f = |x: f64| if x.is_nan() { 0. } else { f(x) };
+    Color::new(f(c.x), f(c.y), f(c.z))
+}
+
+fn hable(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+fn aces_fitted(c: Color) -> Color {
+    // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+    const INPUT: Matrix3 = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    // ODT_SAT => XYZ => D60_2_D65 => sRGB
+    const OUTPUT: Matrix3 = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let c = map(mul_vector(&INPUT, c), |v| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    });
+    mul_vector(&OUTPUT, c)
+}
+
+/// Chromaticity of the light at a colour temperature: the CIE daylight
+/// locus from 4000K up, the Planckian locus (Kim et al.'s fit) below.
+fn white_point(kelvin: f64) -> (f64, f64) {
+    let t = clamp(kelvin, 1667., 25000.);
+    let (t2, t3) = (t * t, t * t * t);
+    if t >= 4000. {
+        let x = if t <= 7000. {
+            -4.6070e9 / t3 + 2.9678e6 / t2 + 0.09911e3 / t + 0.244063
+        } else {
+            -2.0064e9 / t3 + 1.9018e6 / t2 + 0.24748e3 / t + 0.237040
+        };
+        (x, -3. * x * x + 2.87 * x - 0.275)
+    } else {
+        let x = -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910;
+        let y = if t <= 2222. {
+            -1.1063814 * x * x * x - 1.34811020 * x * x + 2.18555832 * x - 0.20219683
+        } else {
+            -0.9549476 * x * x * x - 1.37418593 * x * x + 2.09137015 * x - 0.16748867
+        };
+        (x, y)
+    }
+}
+
+/// Linear sRGB to linear sRGB matrix that makes light of the given
+/// temperature white, adapting it to D65 in the Bradford cone space.
+fn white_balance_matrix(kelvin: f64) -> Matrix3 {
+    const SRGB_TO_XYZ: Matrix3 = [
+        [0.4124564, 0.3575761, 0.1804375],
+        [0.2126729, 0.7151522, 0.0721750],
+        [0.0193339, 0.1191920, 0.9503041],
+    ];
+    const XYZ_TO_SRGB: Matrix3 = [
+        [3.2404542, -1.5371385, -0.4985314],
+        [-0.9692660, 1.8760108, 0.0415560],
+        [0.0556434, -0.2040259, 1.0572252],
+    ];
+    const BRADFORD: Matrix3 = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+    const BRADFORD_INVERSE: Matrix3 = [
+        [0.9869929, -0.1470543, 0.1599627],
+        [0.4323053, 0.5183603, 0.0492912],
+        [-0.0085287, 0.0400428, 0.9684867],
+    ];
+
+    let (x, y) = white_point(kelvin);
+    let source = mul_vector(&BRADFORD, Vec3::new(x / y, 1., (1. - x - y) / y));
+    let target = mul_vector(&BRADFORD, Vec3::new(0.95047, 1., 1.08883));
+    let scale = [
+        [target.x / source.x, 0., 0.],
+        [0., target.y / source.y, 0.],
+        [0., 0., target.z / source.z],
+    ];
+    let adapt = mul_matrix(&BRADFORD_INVERSE, &mul_matrix(&scale, &BRADFORD));
+    mul_matrix(&XYZ_TO_SRGB, &mul_matrix(&adapt, &SRGB_TO_XYZ))
+}
@@ -0,0 +1,163 @@
+use crate::*;
+use std::sync::Arc;
+
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    /// Per-vertex shading normals, interpolated across the face when present.
+    pub normals: Option<[Vec3; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, material: Arc<dyn Material>) -> Self {
+        Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, n0: Vec3, n1: Vec3, n2: Vec3) -> Self {
+        self.normals = Some([n0, n1, n2]);
+        self
+    }
+}
+
+/// Watertight ray/triangle intersection (Woop, Benthin and Wald, 2013).
+///
+/// The vertices are moved into a space where the ray runs along +z from the
+/// origin, so the edge tests of two triangles sharing an edge are computed
+/// from exactly the same values and a ray can't slip between them. Returns
+/// the ray parameter and the barycentric weights of `p0`, `p1` and `p2`.
+pub fn intersect_triangle(
+    ray: &Ray,
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, Vec3)> {
+    let d = ray.direction;
+    let abs_d = Vec3::new(d.x.abs(), d.y.abs(), d.z.abs());
+    let kz = if abs_d.x > abs_d.y && abs_d.x > abs_d.z {
+        0
+    } else if abs_d.y > abs_d.z {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    // keep the winding of the projected triangle
+    if d[kz].is_sign_negative() {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let sx = d[kx] / d[kz];
+    let sy = d[ky] / d[kz];
+    let sz = 1. / d[kz];
+
+    let a = p0 - ray.origin;
+    let b = p1 - ray.origin;
+    let c = p2 - ray.origin;
+
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0. || v < 0. || w < 0.) && (u > 0. || v > 0. || w > 0.) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0. {
+        return None;
+    }
+    // a ray through a shared edge or vertex gives exactly zero there in
+    // every triangle meeting it, so only the one the edge bounds from a
+    // fixed side keeps the hit
+    if (u == 0. && !owns_edge(bx, by, cx, cy, det))
+        || (v == 0. && !owns_edge(cx, cy, ax, ay, det))
+        || (w == 0. && !owns_edge(ax, ay, bx, by, det))
+    {
+        return None;
+    }
+
+    let t = (u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz]) / det;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    Some((t, Vec3::new(u / det, v / det, w / det)))
+}
+
+/// Whether a hit exactly on the projected edge from `(px, py)` to
+/// `(qx, qy)` belongs to the triangle. The edge is turned to run with the
+/// triangle on its right, so the two triangles sharing it see opposite
+/// directions and exactly one of them is in the half-open range accepted.
+fn owns_edge(px: f64, py: f64, qx: f64, qy: f64, det: f64) -> bool {
+    let (dx, dy) = if det > 0. {
+        (qx - px, qy - py)
+    } else {
+        (px - qx, py - qy)
+    };
+    dy < 0. || (dy == 0. && dx > 0.)
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, record: &mut HitRecord) -> bool {
+        let [p0, p1, p2] = self.vertices;
+        let (t, barycentric) = match intersect_triangle(ray, p0, p1, p2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        record.t = t;
+        record.point = barycentric.x * p0 + barycentric.y * p1 + barycentric.z * p2;
+        record.barycentric = barycentric;
+        record.u = barycentric.y;
+        record.v = barycentric.z;
+        record.set_face_normal(ray, (p1 - p0).cross(p2 - p0).unit());
+        if let Some([n0, n1, n2]) = self.normals {
+            record.set_shading_normal(barycentric.x * n0 + barycentric.y * n1 + barycentric.z * n2);
+        }
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let [p0, p1, p2] = self.vertices;
+        *output_box = triangle_box(p0, p1, p2);
+        true
+    }
+
+    fn describe(&self, exporter: &mut SceneExporter) -> bool {
+        match exporter.material(&self.material) {
+            Some(material) => {
+                exporter.add(ObjectDescription::Triangle {
+                    vertices: self.vertices.map(|v| v.into()),
+                    normals: self.normals.map(|normals| normals.map(|n| n.into())),
+                    material,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn add_lights(&self, lights: &mut LightCollector) {
+        lights.add(&self.material, LightShape::Triangle(self.vertices));
+    }
+}
+
+/// Bounds of a triangle, padded so axis-aligned faces don't give a flat box.
+pub fn triangle_box(p0: Point3, p1: Point3, p2: Point3) -> Aabb {
+    const PADDING: f64 = 1e-6;
+    let pad = Vec3::new(PADDING, PADDING, PADDING);
+    Aabb::new(p0.min(p1).min(p2) - pad, p0.max(p1).max(p2) + pad)
+}
@@ -47,16 +47,6 @@ impl Vec3 {
         }
     }
 
-    pub fn to_color_string(self, samples_per_pixel: i32) -> String {
-        let scale = 1. / samples_per_pixel as f64;
-
-        vec![self.x, self.y, self.z]
-            .iter()
-            .map(|n| ((255.999 * clamp((n * scale).sqrt(), 0., 0.999)) as i32).to_string())
-            .collect::<Vec<_>>()
-            .join(" ")
-    }
-
     pub fn random() -> Self {
         Vec3 {
             x: rand_f64(),
@@ -104,6 +94,22 @@ impl Vec3 {
         self - self.dot(normal) * normal * 2.
     }
 
+    pub fn min(self, target: Self) -> Self {
+        Vec3 {
+            x: self.x.min(target.x),
+            y: self.y.min(target.y),
+            z: self.z.min(target.z),
+        }
+    }
+
+    pub fn max(self, target: Self) -> Self {
+        Vec3 {
+            x: self.x.max(target.x),
+            y: self.y.max(target.y),
+            z: self.z.max(target.z),
+        }
+    }
+
     pub fn refract(self, normal: Vec3, etai_over_etat: f64) -> Vec3 {
         let cos_theta = (-self).dot(normal).min(1.0);
         let r_out_perp = etai_over_etat * (self + cos_theta * normal);
@@ -138,12 +144,36 @@ impl convert::From<(f64, f64, f64)> for Vec3 {
     }
 }
 
+impl convert::From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+impl convert::From<Vec3> for [f64; 3] {
+    fn from(v: Vec3) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
 impl fmt::Display for Vec3 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "vec3({}, {},{})", self.x, self.y, self.z)
     }
 }
 
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of range: {}", axis),
+        }
+    }
+}
+
 impl ops::Add<f64> for Vec3 {
     type Output = Self;
     fn add(self, rhs: f64) -> Self::Output {
@@ -240,6 +270,24 @@ impl ops::Div<f64> for Vec3 {
 pub type Point3 = Vec3;
 pub type Color = Vec3;
 
+/// Row-major 3x3 matrix, for rotations and colour space conversions.
+pub type Matrix3 = [[f64; 3]; 3];
+
+pub fn mul_vector(m: &Matrix3, v: Vec3) -> Vec3 {
+    let row = |r: [f64; 3]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+    Vec3::new(row(m[0]), row(m[1]), row(m[2]))
+}
+
+pub fn mul_matrix(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut m = [[0.; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     match true {
         _ if x < min => min,
@@ -0,0 +1,105 @@
+use ray_tracing_utility::*;
+use std::sync::Arc;
+
+fn spheres(count: usize) -> HittableList {
+    let mut rng = Pcg32::new(7, 0);
+    let mut list = HittableList::default();
+    for _ in 0..count {
+        let center = rng.next_vec3_in_range(-5., 5.);
+        list.add(Arc::new(Sphere::new(
+            center,
+            rng.next_f64_in_range(0.05, 0.8),
+        )));
+    }
+    list
+}
+
+fn random_rays(count: usize) -> Vec<Ray> {
+    let mut rng = Pcg32::new(11, 0);
+    (0..count)
+        .map(|_| {
+            let origin = rng.next_vec3_in_range(-8., 8.);
+            // aim roughly at the middle so most rays go through the spheres
+            let target = rng.next_vec3_in_range(-3., 3.);
+            Ray::new(origin, target - origin)
+        })
+        .collect()
+}
+
+/// Checks that `accel` finds the same closest hit as testing every object.
+fn same_hits(list: &HittableList, accel: &dyn Hittable) {
+    let mut hits = 0;
+    for ray in random_rays(2000) {
+        let (mut expected, mut actual) = (HitRecord::default(), HitRecord::default());
+        let hit = list.hit(&ray, 0.001, f64::INFINITY, &mut expected);
+        assert_eq!(accel.hit(&ray, 0.001, f64::INFINITY, &mut actual), hit);
+        assert_eq!(accel.occluded(&ray, 0.001, f64::INFINITY), hit);
+        if hit {
+            hits += 1;
+            assert_eq!(actual.t.to_bits(), expected.t.to_bits());
+            assert_eq!(
+                <[f64; 3]>::from(actual.normal),
+                <[f64; 3]>::from(expected.normal)
+            );
+        }
+    }
+    assert!(hits > 500, "only {} rays hit anything", hits);
+}
+
+#[test]
+fn bvh_node_matches_linear_list() {
+    let list = spheres(300);
+    same_hits(&list, &BvhNode::new(&list));
+}
+
+#[test]
+fn sah_bvh_matches_linear_list() {
+    let list = spheres(300);
+    same_hits(&list, &BvhBuilder::default().build_list(&list));
+    same_hits(
+        &list,
+        &BvhBuilder::default().max_leaf_size(1).build_list(&list),
+    );
+    same_hits(&list, &BvhBuilder::default().bin_count(0).build_list(&list));
+}
+
+/// Checks that every primitive sits in exactly one non-empty leaf of at most
+/// `max_leaf_size` primitives.
+fn check_leaves(bvh: &FlatBvh, max_leaf_size: usize) {
+    let stats = bvh.stats();
+    let histogram = &stats.leaf_size_histogram;
+    assert!(histogram.first().is_none_or(|&empty| empty == 0));
+    assert!(histogram.len() <= max_leaf_size + 1);
+    let leaves = histogram.iter().sum::<usize>();
+    let primitives = histogram
+        .iter()
+        .enumerate()
+        .map(|(size, count)| size * count)
+        .sum::<usize>();
+    assert_eq!(leaves, stats.leaf_count);
+    assert_eq!(primitives, stats.primitive_count);
+    assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+}
+
+#[test]
+fn clamped_leaf_sizes_leave_no_empty_nodes() {
+    let mut rng = Pcg32::new(3, 0);
+    let scattered = (0..500)
+        .map(|_| {
+            let p = rng.next_vec3_in_range(-5., 5.);
+            Aabb::new(p, p + Vec3::new(0.1, 0.1, 0.1))
+        })
+        .collect::<Vec<_>>();
+    // coincident centroids can only be split at the median
+    let stacked = vec![Aabb::new(Point3::new(0., 0., 0.), Point3::new(1., 1., 1.)); 70_000];
+
+    for bounds in [&scattered, &stacked].iter() {
+        check_leaves(&BvhBuilder::default().max_leaf_size(0).build(bounds), 1);
+        check_leaves(
+            &BvhBuilder::default()
+                .max_leaf_size(usize::MAX)
+                .build(bounds),
+            u16::MAX as usize,
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use flate2::read::ZlibDecoder;
+use ray_tracing_utility::*;
+use std::convert::TryInto;
+use std::io::Read;
+
+fn gradient(width: usize, height: usize) -> Film {
+    let mut film = Film::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            film.add_sample(x, y, Color::new(x as f64 * 0.25, y as f64, 0.5));
+        }
+    }
+    film
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn cstr(bytes: &[u8], at: &mut usize) -> String {
+    let end = *at + bytes[*at..].iter().position(|&b| b == 0).unwrap();
+    let s = String::from_utf8(bytes[*at..end].to_vec()).unwrap();
+    *at = end + 1;
+    s
+}
+
+/// Parses the header into (name, type, value) triples and returns them with
+/// the offset just past it.
+fn header(exr: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+    assert_eq!(u32_at(exr, 0), 20000630);
+    assert_eq!(u32_at(exr, 4), 2);
+    let mut at = 8;
+    let mut attributes = Vec::new();
+    while exr[at] != 0 {
+        let name = cstr(exr, &mut at);
+        let kind = cstr(exr, &mut at);
+        let size = u32_at(exr, at) as usize;
+        attributes.push((name, kind, exr[at + 4..at + 4 + size].to_vec()));
+        at += 4 + size;
+    }
+    (attributes, at + 1)
+}
+
+fn channel_list() -> Vec<u8> {
+    let mut channels = Vec::new();
+    for name in [b'B', b'G', b'R'].iter() {
+        channels.extend_from_slice(&[*name, 0]);
+        channels.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+    }
+    channels.push(0);
+    channels
+}
+
+/// Undoes the predictor and the byte interleaving of the ZIP scheme.
+fn unzip(data: &[u8]) -> Vec<u8> {
+    let mut shuffled = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut shuffled).unwrap();
+    for i in 1..shuffled.len() {
+        shuffled[i] = shuffled[i].wrapping_add(shuffled[i - 1]).wrapping_sub(128);
+    }
+    let half = shuffled.len().div_ceil(2);
+    (0..shuffled.len())
+        .map(|i| {
+            if i % 2 == 0 {
+                shuffled[i / 2]
+            } else {
+                shuffled[half + i / 2]
+            }
+        })
+        .collect()
+}
+
+fn check(compression: ExrCompression, id: u8, lines: usize) {
+    let (width, height) = (16, 20);
+    let film = gradient(width, height);
+    let mut exr = Vec::new();
+    write_exr(&film, &mut exr, compression).unwrap();
+
+    let (attributes, end) = header(&exr);
+    let window = [0i32, 0, 15, 19]
+        .iter()
+        .flat_map(|n| n.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+    let expected = vec![
+        ("channels", "chlist", channel_list()),
+        ("compression", "compression", vec![id]),
+        ("dataWindow", "box2i", window.clone()),
+        ("displayWindow", "box2i", window),
+        ("lineOrder", "lineOrder", vec![0]),
+        ("pixelAspectRatio", "float", 1f32.to_le_bytes().to_vec()),
+        ("screenWindowCenter", "v2f", vec![0; 8]),
+        ("screenWindowWidth", "float", 1f32.to_le_bytes().to_vec()),
+    ];
+    assert_eq!(attributes.len(), expected.len());
+    for ((name, kind, value), (e_name, e_kind, e_value)) in attributes.iter().zip(expected) {
+        assert_eq!((name.as_str(), kind.as_str()), (e_name, e_kind));
+        assert_eq!(value, &e_value, "{}", name);
+    }
+
+    let blocks = height.div_ceil(lines);
+    let mut compressed = false;
+    for block in 0..blocks {
+        let at = end + block * 8;
+        let offset = u64::from_le_bytes(exr[at..at + 8].try_into().unwrap()) as usize;
+        let y0 = u32_at(&exr, offset) as usize;
+        let size = u32_at(&exr, offset + 4) as usize;
+        assert_eq!(y0, block * lines);
+        let data = &exr[offset + 8..offset + 8 + size];
+        let lines_here = lines.min(height - y0);
+        let raw_size = lines_here * width * 12;
+        let raw = if size < raw_size {
+            compressed = true;
+            unzip(data)
+        } else {
+            data.to_vec()
+        };
+        assert_eq!(raw.len(), raw_size);
+
+        // each line holds a row of B, then of G, then of R
+        let floats = raw
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        for (line, values) in floats.chunks_exact(width * 3).enumerate() {
+            for x in 0..width {
+                let pixel = film.pixel(x, y0 + line);
+                for (i, c) in [2, 1, 0].iter().enumerate() {
+                    assert_eq!(values[i * width + x], pixel[*c] as f32);
+                }
+            }
+        }
+        if block + 1 == blocks {
+            assert_eq!(offset + 8 + size, exr.len());
+        }
+    }
+    assert_eq!(compressed, compression == ExrCompression::Zip);
+}
+
+#[test]
+fn uncompressed_scanlines() {
+    check(ExrCompression::None, 0, 1);
+}
+
+#[test]
+fn zip_compressed_blocks() {
+    check(ExrCompression::Zip, 3, 16);
+}
@@ -0,0 +1,53 @@
+use ray_tracing_utility::*;
+
+const HEADER: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n";
+
+#[test]
+fn flat_rgbe_pixels() {
+    let mut film = Film::new(3, 1);
+    film.add_sample(0, 0, Color::new(1., 0.5, 0.25));
+    film.add_sample(2, 0, Color::new(-1., 3., 0.));
+    let mut hdr = Vec::new();
+    write_hdr(&film, &mut hdr).unwrap();
+
+    let header = [HEADER, b"-Y 1 +X 3\n"].concat();
+    assert_eq!(&hdr[..header.len()], &header[..]);
+    // too narrow for run-length encoding: 1.0 is 128/256 * 2^1
+    assert_eq!(
+        &hdr[header.len()..],
+        [128, 64, 32, 129, 0, 0, 0, 0, 0, 192, 0, 130]
+    );
+}
+
+#[test]
+fn run_length_encoded_scanlines() {
+    let (width, height) = (21, 2);
+    let mut film = Film::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            // long runs of equal bytes and literals
+            let r = if x < 12 { 0.5 } else { x as f64 };
+            film.add_sample(x, y, Color::new(r, (x % 3) as f64, y as f64 * 0.25));
+        }
+    }
+    let mut hdr = Vec::new();
+    write_hdr(&film, &mut hdr).unwrap();
+
+    let header = [HEADER, b"-Y 2 +X 21\n"].concat();
+    assert_eq!(&hdr[..header.len()], &header[..]);
+    assert_eq!(&hdr[header.len()..header.len() + 4], [2, 2, 0, 21]);
+    assert!(hdr.len() < header.len() + width * height * 4);
+
+    let image = read_hdr(&hdr[..]).unwrap();
+    assert_eq!((image.width(), image.height()), (width, height));
+    for y in 0..height {
+        for x in 0..width {
+            let (read, written) = (image.get(x, y), film.pixel(x, y));
+            // within half a mantissa step of the shared exponent
+            let step = written.x.max(written.y).max(written.z) / 128.;
+            for c in 0..3 {
+                assert!((read[c] - written[c]).abs() <= step, "pixel {},{}", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use flate2::read::ZlibDecoder;
+use ray_tracing_utility::*;
+use std::io::Read;
+
+fn fixture(transfer: TransferFunction) -> RgbImage {
+    let pixels = (0..12u8)
+        .map(|i| [i * 20, 255 - i * 7, (i * i) % 251])
+        .collect::<Vec<_>>();
+    RgbImage::from_pixels(4, 3, transfer, &pixels)
+}
+
+/// Splits a PNG into its chunks, checking the signature and every CRC.
+fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut chunks = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = [rest[4], rest[5], rest[6], rest[7]];
+        let data = &rest[8..8 + length];
+        let crc = &rest[8 + length..12 + length];
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&kind);
+        hasher.update(data);
+        assert_eq!(crc, hasher.finalize().to_be_bytes(), "bad crc");
+        chunks.push((kind, data.to_vec()));
+        rest = &rest[12 + length..];
+    }
+    chunks
+}
+
+/// Inflates and unfilters the image data of a truecolour 8-bit PNG.
+fn decode(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut filtered = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut filtered).unwrap();
+    let stride = width * 3;
+    assert_eq!(filtered.len(), height * (stride + 1));
+    let mut pixels: Vec<u8> = Vec::new();
+    for (y, line) in filtered.chunks_exact(stride + 1).enumerate() {
+        for i in 0..stride {
+            let a = if i >= 3 {
+                pixels[y * stride + i - 3]
+            } else {
+                0
+            };
+            let b = if y > 0 {
+                pixels[(y - 1) * stride + i]
+            } else {
+                0
+            };
+            let c = if i >= 3 && y > 0 {
+                pixels[(y - 1) * stride + i - 3]
+            } else {
+                0
+            };
+            let predicted = match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => {
+                    let p = a as i16 + b as i16 - c as i16;
+                    let (pa, pb, pc) = (
+                        (p - a as i16).abs(),
+                        (p - b as i16).abs(),
+                        (p - c as i16).abs(),
+                    );
+                    if pa <= pb && pa <= pc {
+                        a
+                    } else if pb <= pc {
+                        b
+                    } else {
+                        c
+                    }
+                }
+                filter => panic!("unknown filter {}", filter),
+            };
+            pixels.push(line[1 + i].wrapping_add(predicted));
+        }
+    }
+    pixels
+}
+
+#[test]
+fn png_with_srgb_chunks() {
+    let image = fixture(TransferFunction::Srgb);
+    let mut png = Vec::new();
+    image.write_png(&mut png).unwrap();
+    let chunks = chunks(&png);
+    let kinds = chunks.iter().map(|(kind, _)| kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [b"IHDR", b"sRGB", b"gAMA", b"IDAT", b"IEND"]);
+
+    assert_eq!(chunks[0].1, [0, 0, 0, 4, 0, 0, 0, 3, 8, 2, 0, 0, 0]);
+    // perceptual rendering intent, followed by the gAMA value sRGB decoders expect
+    assert_eq!(chunks[1].1, [0]);
+    assert_eq!(chunks[2].1, 45455u32.to_be_bytes());
+    assert_eq!(decode(&chunks[3].1, 4, 3), image.as_bytes());
+    assert!(chunks[4].1.is_empty());
+}
+
+#[test]
+fn png_with_plain_gamma() {
+    let image = fixture(TransferFunction::Gamma(2.));
+    let mut png = Vec::new();
+    image.write_png(&mut png).unwrap();
+    let chunks = chunks(&png);
+    let kinds = chunks.iter().map(|(kind, _)| kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [b"IHDR", b"gAMA", b"IDAT", b"IEND"]);
+    assert_eq!(chunks[1].1, 50000u32.to_be_bytes());
+    assert_eq!(decode(&chunks[2].1, 4, 3), image.as_bytes());
+}
+
+#[test]
+fn png_rejects_empty_image() {
+    let image = RgbImage::new(0, 3, TransferFunction::Srgb);
+    assert!(image.write_png(Vec::new()).is_err());
+}
+
+#[test]
+fn binary_ppm() {
+    let image = fixture(TransferFunction::Srgb);
+    let mut ppm = Vec::new();
+    image.write_ppm(&mut ppm).unwrap();
+    let header = b"P6\n4 3\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    assert_eq!(&ppm[header.len()..], image.as_bytes());
+}
@@ -0,0 +1,171 @@
+use ray_tracing_utility::*;
+use std::sync::Arc;
+
+const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float u
+property float v
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+";
+
+// unit quad in the z = 0 plane, facing +z
+const VERTICES: [[f32; 8]; 4] = [
+    [0., 0., 0., 0., 0., 1., 0., 0.],
+    [1., 0., 0., 0., 0., 1., 1., 0.],
+    [1., 1., 0., 0., 0., 1., 1., 1.],
+    [0., 1., 0., 0., 0., 1., 0., 1.],
+];
+const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+fn material() -> Arc<dyn Material> {
+    Lambertian::new(128, 128, 128).as_ref()
+}
+
+fn ascii_fixture() -> Vec<u8> {
+    let mut ply = format!("ply\nformat ascii 1.0\ncomment hand written\n{}", HEADER);
+    for (v, c) in VERTICES.iter().zip(COLORS.iter()) {
+        let v = v.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        ply.push_str(&format!("{} {} {} {}\n", v.join(" "), c[0], c[1], c[2]));
+    }
+    ply.push_str("4 0 1 2 3\n0 2\n");
+    ply.into_bytes()
+}
+
+fn binary_fixture(big_endian: bool) -> Vec<u8> {
+    let format = if big_endian {
+        "binary_big_endian"
+    } else {
+        "binary_little_endian"
+    };
+    let mut ply = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+    let f32_bytes = |x: f32| {
+        if big_endian {
+            x.to_be_bytes()
+        } else {
+            x.to_le_bytes()
+        }
+    };
+    let i32_bytes = |x: i32| {
+        if big_endian {
+            x.to_be_bytes()
+        } else {
+            x.to_le_bytes()
+        }
+    };
+    for (v, c) in VERTICES.iter().zip(COLORS.iter()) {
+        for &x in v.iter() {
+            ply.extend_from_slice(&f32_bytes(x));
+        }
+        ply.extend_from_slice(c);
+    }
+    ply.push(4);
+    for i in 0..4 {
+        ply.extend_from_slice(&i32_bytes(i));
+    }
+    ply.extend_from_slice(&i32_bytes(0));
+    ply.extend_from_slice(&i32_bytes(2));
+    ply
+}
+
+fn check_quad(mesh: &TriangleMesh) {
+    assert_eq!(mesh.face_count(), 2);
+    assert_eq!(mesh.positions().len(), 4);
+    assert_eq!(mesh.positions()[2].x, 1.);
+    assert_eq!(mesh.positions()[2].y, 1.);
+    assert_eq!(mesh.normals().len(), 4);
+    assert_eq!(mesh.normals()[0].z, 1.);
+    assert_eq!(mesh.uvs(), &[[0., 0.], [1., 0.], [1., 1.], [0., 1.]]);
+    assert_eq!(mesh.colors().len(), 4);
+    assert_eq!(mesh.colors()[0].x, 1.);
+    assert_eq!(mesh.colors()[1].x, 0.);
+    assert_eq!(mesh.colors()[3].z, 1.);
+
+    let ray = Ray::new(Point3::new(0.25, 0.75, 1.), Vec3::new(0., 0., -1.));
+    let mut record = HitRecord::default();
+    assert!(mesh.hit(&ray, 0.001, f64::INFINITY, &mut record));
+    assert!((record.t - 1.).abs() < 1e-9);
+    assert!((record.u - 0.25).abs() < 1e-9);
+    assert!((record.v - 0.75).abs() < 1e-9);
+    assert!(record.front_face);
+}
+
+#[test]
+fn reads_ascii() {
+    check_quad(&parse_ply(&ascii_fixture(), material()).unwrap());
+}
+
+#[test]
+fn reads_binary_little_endian() {
+    check_quad(&parse_ply(&binary_fixture(false), material()).unwrap());
+}
+
+#[test]
+fn reads_binary_big_endian() {
+    check_quad(&parse_ply(&binary_fixture(true), material()).unwrap());
+}
+
+#[test]
+fn rejects_out_of_range_index() {
+    let ply = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+1 0 0
+0 1 0
+3 0 1 3
+";
+    match parse_ply(ply.as_bytes(), material()) {
+        Err(PlyError::Data(message)) => assert!(message.contains("vertex 3")),
+        _ => panic!("expected a data error"),
+    }
+}
+
+#[test]
+fn rejects_index_that_is_not_an_integer() {
+    let ascii = String::from_utf8(ascii_fixture()).unwrap();
+    for index in ["1.5", "nan", "-0.5", "inf"].iter() {
+        let ply = ascii.replace("4 0 1 2 3\n", &format!("4 0 1 {} 3\n", index));
+        match parse_ply(ply.as_bytes(), material()) {
+            Err(PlyError::Data(message)) => assert!(message.contains("not an integer")),
+            _ => panic!("index {} was accepted", index),
+        }
+    }
+}
+
+#[test]
+fn rejects_truncated_binary() {
+    let mut ply = binary_fixture(false);
+    ply.truncate(ply.len() - 6);
+    assert!(matches!(
+        parse_ply(&ply, material()),
+        Err(PlyError::Data(_))
+    ));
+}
+
+#[test]
+fn reports_header_line() {
+    let ply = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float128 x\nend_header\n";
+    match parse_ply(ply.as_bytes(), material()) {
+        Err(PlyError::Header { line, .. }) => assert_eq!(line, 4),
+        _ => panic!("expected a header error"),
+    }
+}
@@ -0,0 +1,96 @@
+use ray_tracing_utility::*;
+use std::sync::Arc;
+
+fn world() -> HittableList {
+    let mut world = HittableList::default();
+    let spheres: [(Point3, f64, Arc<dyn Material>); 5] = [
+        (
+            Point3::new(0., -100.5, -1.),
+            100.,
+            Lambertian::new(200, 200, 50).as_ref(),
+        ),
+        (
+            Point3::new(0., 0., -1.),
+            0.5,
+            Lambertian::new(25, 50, 128).as_ref(),
+        ),
+        (
+            Point3::new(-1., 0., -1.),
+            0.5,
+            Dielectric::new(1.5).as_ref(),
+        ),
+        (
+            Point3::new(1., 0., -1.),
+            0.5,
+            Metal::new(200, 150, 50, 0.3).as_ref(),
+        ),
+        (
+            Point3::new(0., 2., -1.),
+            0.5,
+            DiffuseLight::new(Color::new(4., 4., 4.)).as_ref(),
+        ),
+    ];
+    for (center, radius, material) in spheres.iter().cloned() {
+        world.add(Arc::new(Sphere {
+            center,
+            radius,
+            material,
+        }));
+    }
+    world
+}
+
+fn render(sampler: SamplerKind, threads: usize) -> Film {
+    let settings = RenderSettings {
+        width: 48,
+        samples_per_pixel: 8,
+        samples_per_pass: 3,
+        max_depth: 8,
+        sampler,
+        seed: 42,
+        tile_size: 8,
+        ..RenderSettings::default()
+    };
+    let world = BvhBuilder::default().build_list(&world());
+    let camera = Camera::new(
+        Point3::new(0., 0.5, 2.),
+        Point3::new(0., 0., -1.),
+        Vec3::new(0., 1., 0.),
+        40.,
+        settings.aspect_ratio,
+        0.1,
+        3.,
+    );
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    pool.install(|| Renderer::new(&world, &camera, settings).render())
+}
+
+#[test]
+fn same_image_on_any_number_of_threads() {
+    for sampler in [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ] {
+        let (one, many) = (render(sampler, 1), render(sampler, 4));
+        for y in 0..one.height() {
+            for x in 0..one.width() {
+                let (a, b) = (one.samples(x, y), many.samples(x, y));
+                assert_eq!(a.count, b.count);
+                assert_eq!(
+                    <[f64; 3]>::from(a.radiance).map(f64::to_bits),
+                    <[f64; 3]>::from(b.radiance).map(f64::to_bits),
+                    "{:?} differs at ({}, {})",
+                    sampler,
+                    x,
+                    y
+                );
+                assert_eq!(a.luminance_squared.to_bits(), b.luminance_squared.to_bits());
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use ray_tracing_utility::*;
+use std::path::Path;
+
+// values picked to need every digit of their shortest representation
+const SCENE: &str = r#"{
+  "render": {
+    "width": 64,
+    "aspect_ratio": 1.7777777777777777,
+    "samples_per_pixel": 7,
+    "max_depth": 9,
+    "sampler": "sobol",
+    "seed": 12345678901234,
+    "tone_mapping": { "exposure": -0.30000000000000004, "white_balance": 5003.7 }
+  },
+  "camera": {
+    "look_from": [-2.1, 2.0000000000000004, 1e-7],
+    "look_at": [0.1, 0.2, -1.0],
+    "vup": [0.01, 1.0, 0.0],
+    "v_fov": 33.333333333333336,
+    "aperture": 0.1,
+    "focus_dist": 3.7
+  },
+  "materials": {
+    "ground": { "type": "lambertian", "albedo": [0.8, 0.8, 0.1] },
+    "glass": { "type": "dielectric", "refraction_index": 1.3333333333333333 },
+    "gold": { "type": "metal", "albedo": [0.8, 0.6, 0.2], "fuzz": 0.07 },
+    "lamp": { "type": "diffuse_light", "emit": [4.4, 4.4, 3.3] }
+  },
+  "objects": [
+    { "type": "sphere", "center": [0, -100.5, -1], "radius": 100, "material": "ground" },
+    { "type": "sphere", "center": [-1, 0, -1], "radius": -0.45, "material": "glass" },
+    { "type": "sphere", "center": [1.1, 0.3, -1.7], "radius": 0.5, "material": "gold" },
+    {
+      "type": "triangle",
+      "vertices": [[0, 1, -2], [1, 1.5, -2], [0.5, 2.2, -2.1]],
+      "material": "lamp"
+    },
+    {
+      "type": "mesh",
+      "positions": [[0, 0, -3], [1, 0, -3], [1, 1, -3.3], [0, 1, -3]],
+      "indices": [[0, 1, 2], [0, 2, 3]],
+      "uvs": [[0, 0], [1, 0], [1, 1], [0, 1]],
+      "material": "ground"
+    }
+  ],
+  "background": { "gradient": { "bottom": [1, 0.9, 0.8], "top": [0.2, 0.3, 0.7], "rotation": [0, 12.5, 0] } }
+}"#;
+
+#[test]
+fn export_round_trips() {
+    let path = Path::new("round-trip.json");
+    let first = Scene::parse(SCENE, path)
+        .unwrap()
+        .describe()
+        .unwrap()
+        .to_json();
+    let second = Scene::parse(&first, path)
+        .unwrap()
+        .describe()
+        .unwrap()
+        .to_json();
+    assert_eq!(first, second);
+
+    // the values come back bit for bit
+    let exported: serde_json::Value = serde_json::from_str(&first).unwrap();
+    let original: serde_json::Value = serde_json::from_str(SCENE).unwrap();
+    assert_eq!(exported["camera"], original["camera"]);
+    assert_eq!(
+        exported["render"]["tone_mapping"]["exposure"],
+        original["render"]["tone_mapping"]["exposure"]
+    );
+    assert_eq!(exported["render"]["seed"], original["render"]["seed"]);
+}
+
+/// Builds `SCENE` after `change` and returns the key of the invalid value.
+fn invalid_key(change: impl FnOnce(&mut SceneDescription)) -> String {
+    let mut description: SceneDescription = serde_json::from_str(SCENE).unwrap();
+    change(&mut description);
+    match description.build(Path::new("invalid.json")) {
+        Err(SceneError::Invalid { key, .. }) => key,
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("scene was accepted"),
+    }
+}
+
+#[test]
+fn rejects_values_that_are_not_numbers() {
+    assert_eq!(
+        invalid_key(|d| {
+            d.materials.insert(
+                String::from("glass"),
+                MaterialDescription::Dielectric {
+                    refraction_index: f64::NAN,
+                },
+            );
+        }),
+        "materials.glass"
+    );
+    for radius in [f64::NAN, f64::INFINITY, 0.].iter() {
+        let key = invalid_key(|d| {
+            if let ObjectDescription::Sphere { radius: r, .. } = &mut d.objects[1] {
+                *r = *radius;
+            }
+        });
+        assert_eq!(key, "objects[1].radius");
+    }
+    assert_eq!(
+        invalid_key(|d| d.camera.look_from[1] = f64::NAN),
+        "camera.look_from"
+    );
+}
+
+#[test]
+fn rejects_vup_along_the_view_direction() {
+    let parallel = |d: &mut SceneDescription| {
+        let (from, at) = (d.camera.look_from, d.camera.look_at);
+        d.camera.vup = [at[0] - from[0], at[1] - from[1], at[2] - from[2]];
+    };
+    assert_eq!(invalid_key(parallel), "camera.vup");
+    assert_eq!(invalid_key(|d| d.camera.vup = [0.; 3]), "camera.vup");
+    // looking straight down with the default vup
+    assert_eq!(
+        invalid_key(|d| {
+            d.camera.look_from = [0., 5., 0.];
+            d.camera.look_at = [0., 0., 0.];
+            d.camera.vup = [0., 1., 0.];
+        }),
+        "camera.vup"
+    );
+}
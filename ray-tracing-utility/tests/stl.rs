@@ -0,0 +1,152 @@
+use ray_tracing_utility::*;
+use std::sync::Arc;
+
+fn material() -> Arc<dyn Material> {
+    Lambertian::new(128, 128, 128).as_ref()
+}
+
+/// Unit cube made of 12 outward facing triangles.
+fn cube() -> Vec<[[f32; 3]; 3]> {
+    let mut triangles = Vec::new();
+    for axis in 0..3 {
+        for &side in [0., 1.].iter() {
+            let corner = |a: f32, b: f32| {
+                let mut p = [0.; 3];
+                p[axis] = side;
+                p[(axis + 1) % 3] = a;
+                p[(axis + 2) % 3] = b;
+                p
+            };
+            let (p0, p1, p2, p3) = (
+                corner(0., 0.),
+                corner(1., 0.),
+                corner(1., 1.),
+                corner(0., 1.),
+            );
+            if side > 0. {
+                triangles.push([p0, p1, p2]);
+                triangles.push([p0, p2, p3]);
+            } else {
+                triangles.push([p0, p2, p1]);
+                triangles.push([p0, p3, p2]);
+            }
+        }
+    }
+    triangles
+}
+
+fn ascii_stl(triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+    let mut stl = String::from("solid test\n");
+    for triangle in triangles.iter() {
+        stl.push_str("  facet normal 0 0 0\n    outer loop\n");
+        for [x, y, z] in triangle.iter() {
+            stl.push_str(&format!("      vertex {:e} {:e} {:e}\n", x, y, z));
+        }
+        stl.push_str("    endloop\n  endfacet\n");
+    }
+    stl.push_str("endsolid test\n");
+    stl.into_bytes()
+}
+
+fn binary_stl(triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+    // exporters often start the header with "solid" as well
+    let mut stl = b"solid exported by a CAD package".to_vec();
+    stl.resize(80, b' ');
+    stl.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for triangle in triangles.iter() {
+        stl.extend_from_slice(&[0; 12]);
+        for p in triangle.iter().flatten() {
+            stl.extend_from_slice(&p.to_le_bytes());
+        }
+        stl.extend_from_slice(&[0; 2]);
+    }
+    stl
+}
+
+fn faceted() -> StlOptions {
+    StlOptions {
+        crease_angle: 0.,
+        ..StlOptions::default()
+    }
+}
+
+#[test]
+fn ascii_and_binary() {
+    let ascii = parse_stl(&ascii_stl(&cube()), material(), faceted()).unwrap();
+    let binary = parse_stl(&binary_stl(&cube()), material(), faceted()).unwrap();
+    for mesh in [&ascii, &binary].iter() {
+        assert_eq!(mesh.face_count(), 12);
+        assert_eq!(mesh.positions().len(), 8);
+    }
+    assert_eq!(ascii.indices(), binary.indices());
+    for (a, b) in ascii.positions().iter().zip(binary.positions()) {
+        assert_eq!(<[f64; 3]>::from(*a), <[f64; 3]>::from(*b));
+    }
+
+    // a binary file whose size doesn't match its count is read as text
+    let mut truncated = binary_stl(&cube());
+    truncated.pop();
+    assert!(matches!(
+        parse_stl(&truncated, material(), faceted()),
+        Err(StlError::Ascii { .. })
+    ));
+}
+
+#[test]
+fn welding_within_tolerance() {
+    // every copy of a corner is moved by its own small offset
+    let mut jittered = cube();
+    for (i, p) in jittered.iter_mut().flatten().enumerate() {
+        p[i % 3] += 1e-6 * (i % 5) as f32;
+    }
+    let welded = parse_stl(&binary_stl(&jittered), material(), faceted()).unwrap();
+    assert_eq!(welded.positions().len(), 8);
+    assert_eq!(welded.face_count(), 12);
+
+    let options = StlOptions {
+        weld_tolerance: 1e-9,
+        ..faceted()
+    };
+    let apart = parse_stl(&binary_stl(&jittered), material(), options).unwrap();
+    assert!(apart.positions().len() > 8);
+}
+
+#[test]
+fn creases_split_sharp_edges_only() {
+    // the cube's 90 degree edges are all sharper than the default 30
+    let cube = parse_stl(&binary_stl(&cube()), material(), StlOptions::default()).unwrap();
+    assert_eq!(cube.positions().len(), 24);
+    for normal in cube.normals().iter() {
+        let n = <[f64; 3]>::from(*normal);
+        assert_eq!(n.iter().filter(|c| c.abs() == 1.).count(), 1, "{:?}", n);
+    }
+
+    // two faces folded by about 11 degrees share their edge vertices
+    let folded = [
+        [[0., 0., 0.], [0., 1., 0.], [-1., 0., 0.]],
+        [[0., 0., 0.], [1., 0., 0.2], [0., 1., 0.]],
+    ];
+    let mesh = parse_stl(&binary_stl(&folded), material(), StlOptions::default()).unwrap();
+    assert_eq!(mesh.positions().len(), 4);
+    let [a, b, _] = mesh.indices()[0];
+    let [c, _, d] = mesh.indices()[1];
+    assert_eq!((a, b), (c, d));
+    let n = mesh.normals()[a as usize];
+    // between (0, 0, 1) and the other face's (-0.2, 0, 1)
+    assert!(n.x < 0. && n.x > -0.2 && n.z > 0.99);
+}
+
+#[test]
+fn back_to_back_faces_keep_a_normal() {
+    let triangle = [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]];
+    let flipped = [triangle[0], triangle[2], triangle[1]];
+    let options = StlOptions {
+        crease_angle: 180.,
+        ..StlOptions::default()
+    };
+    let mesh = parse_stl(&binary_stl(&[triangle, flipped]), material(), options).unwrap();
+    assert_eq!(mesh.face_count(), 2);
+    for normal in mesh.normals().iter() {
+        assert!((normal.length() - 1.).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,66 @@
+use ray_tracing_utility::*;
+
+/// Counts how many of `triangles` a ray from a random point above or below
+/// the z = 0 plane towards `target` hits.
+fn hits(rng: &mut Pcg32, triangles: &[[Point3; 3]], target: Point3) -> usize {
+    let mut origin = rng.next_vec3_in_range(-3., 3.);
+    origin.z = if origin.z < 0. {
+        origin.z - 1.
+    } else {
+        origin.z + 1.
+    };
+    let ray = Ray::new(origin, target - origin);
+    triangles
+        .iter()
+        .filter(|[p0, p1, p2]| intersect_triangle(&ray, *p0, *p1, *p2, 0., f64::INFINITY).is_some())
+        .count()
+}
+
+#[test]
+fn shared_edge_is_hit_once() {
+    let mut rng = Pcg32::new(5, 0);
+    let (a, b, c, d) = (
+        Point3::new(0., 0., 0.),
+        Point3::new(1., 0.1, 0.),
+        Point3::new(0.9, 1., 0.),
+        Point3::new(-0.2, 0.8, 0.),
+    );
+    // the winding of the neighbour mustn't matter
+    for quad in [
+        [[a, b, c], [a, c, d]],
+        [[a, b, c], [c, a, d]],
+        [[c, b, a], [a, c, d]],
+    ]
+    .iter()
+    {
+        for _ in 0..5000 {
+            let s = rng.next_f64();
+            let target = a + s * (c - a);
+            assert_eq!(hits(&mut rng, quad, target), 1);
+        }
+    }
+}
+
+#[test]
+fn shared_vertex_is_hit_once() {
+    let mut rng = Pcg32::new(6, 0);
+    let center = Point3::new(0.3, 0.2, 0.);
+    let ring = (0..7)
+        .map(|i| {
+            let angle = i as f64 * 2. * std::f64::consts::PI / 7. + 0.1 * (i % 2) as f64;
+            center + Vec3::new(angle.cos(), angle.sin(), 0.)
+        })
+        .collect::<Vec<_>>();
+    let fan = (0..ring.len())
+        .map(|i| [center, ring[i], ring[(i + 1) % ring.len()]])
+        .collect::<Vec<_>>();
+    for _ in 0..5000 {
+        assert_eq!(hits(&mut rng, &fan, center), 1);
+    }
+    // and every ring vertex, shared by two triangles of the fan
+    for _ in 0..5000 {
+        let i = (rng.next_u32() % 7) as usize;
+        let owners = hits(&mut rng, &fan, ring[i]);
+        assert!(owners <= 1, "vertex {} hit {} times", i, owners);
+    }
+}